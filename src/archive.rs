@@ -0,0 +1,147 @@
+/// Characters that aren't allowed in file names on some platforms, or make a
+/// zip entry end up in a directory
+const UNSAFE_CHARS: &'static [char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// A file name for a document with the given title, e.g. "Plans/2018" -> "Plans_2018.txt"
+pub fn file_name(title: &str, extension: &str) -> String {
+    let name: String = title.trim()
+        .chars()
+        .map(|c| if UNSAFE_CHARS.contains(&c) || c.is_control() { '_' } else { c })
+        .collect();
+    let name = if name.is_empty() { "untitled".to_string() } else { name };
+    format!("{}.{}", name, extension)
+}
+
+/// The title for a document read from a file, e.g. "Plans.txt" -> "Plans"
+pub fn title_of(file_name: &str) -> String {
+    let name = file_name.rsplit(|c| c == '/' || c == '\\').next().unwrap_or(file_name);
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => name[..dot].to_string(),
+        _ => name.to_string()
+    }
+}
+
+/// A zip archive of the documents (title, content), as text files
+pub fn export(documents: &[(String, String)]) -> Vec<u8> {
+    let mut files: Vec<(String, String)> = vec![];
+    for &(ref title, ref content) in documents {
+        let mut name = file_name(title, "txt");
+        // titles differing only in unsafe characters
+        let mut copy = 1;
+        while files.iter().any(|&(ref taken, _)| *taken == name) {
+            copy += 1;
+            name = file_name(&format!("{} ({})", title, copy), "txt");
+        }
+        files.push((name, content.clone()));
+    }
+    zip(&files)
+}
+
+/// A zip archive of the files (name, content), stored without compression,
+/// which any zip tool can unpack
+pub fn zip(files: &[(String, String)]) -> Vec<u8> {
+    let mut archive = vec![];
+    let mut directory = vec![];
+    for &(ref name, ref content) in files {
+        let offset = archive.len() as u32;
+        let crc = crc32(content.as_bytes());
+        let size = content.len() as u32;
+
+        push_u32(&mut archive, 0x0403_4b50);
+        push_entry_header(&mut archive, name, crc, size);
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(content.as_bytes());
+
+        push_u32(&mut directory, 0x0201_4b50);
+        push_u16(&mut directory, 20); // made by version 2.0
+        push_entry_header(&mut directory, name, crc, size);
+        push_u16(&mut directory, 0); // comment length
+        push_u16(&mut directory, 0); // disk number
+        push_u16(&mut directory, 0); // internal attributes
+        push_u32(&mut directory, 0); // external attributes
+        push_u32(&mut directory, offset);
+        directory.extend_from_slice(name.as_bytes());
+    }
+
+    let directory_offset = archive.len() as u32;
+    let directory_size = directory.len() as u32;
+    archive.extend(directory);
+    push_u32(&mut archive, 0x0605_4b50);
+    push_u16(&mut archive, 0); // this disk
+    push_u16(&mut archive, 0); // disk with the directory
+    push_u16(&mut archive, files.len() as u16);
+    push_u16(&mut archive, files.len() as u16);
+    push_u32(&mut archive, directory_size);
+    push_u32(&mut archive, directory_offset);
+    push_u16(&mut archive, 0); // comment length
+    archive
+}
+
+/// The part shared by local headers and the central directory
+fn push_entry_header(bytes: &mut Vec<u8>, name: &str, crc: u32, size: u32) {
+    push_u16(bytes, 20); // needs version 2.0
+    push_u16(bytes, 0x0800); // utf-8 names
+    push_u16(bytes, 0); // stored
+    push_u16(bytes, 0); // time 00:00
+    push_u16(bytes, 0x21); // date 1980-01-01
+    push_u32(bytes, crc);
+    push_u32(bytes, size); // compressed
+    push_u32(bytes, size);
+    push_u16(bytes, name.len() as u16);
+    push_u16(bytes, 0); // extra field length
+}
+
+fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.push(value as u8);
+    bytes.push((value >> 8) as u8);
+}
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    push_u16(bytes, value as u16);
+    push_u16(bytes, (value >> 16) as u16);
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names() {
+        assert_eq!("Plans_2018.txt", file_name("Plans/2018", "txt"));
+        assert_eq!("untitled.txt", file_name(" ", "txt"));
+        assert_eq!("Plans", title_of("Plans.txt"));
+        assert_eq!("Plans.old", title_of("C:\\docs\\Plans.old.txt"));
+        assert_eq!(".hidden", title_of(".hidden"));
+    }
+
+    #[test]
+    fn zip_archive() {
+        assert_eq!(0xcbf4_3926, crc32(b"123456789"));
+
+        let files = vec![("a.txt".to_string(), "- one\n".to_string()),
+                         ("b.txt".to_string(), "".to_string())];
+        let archive = zip(&files);
+        assert_eq!(&[0x50, 0x4b, 0x03, 0x04], &archive[..4]);
+        // local headers and data, directory entries, end record
+        assert_eq!(2 * 30 + 10 + 6 + 2 * 46 + 10 + 22, archive.len());
+        let end = &archive[archive.len() - 22..];
+        assert_eq!(&[0x50, 0x4b, 0x05, 0x06], &end[..4]);
+        assert_eq!(2, end[10]);
+
+        let documents = vec![("a/b".to_string(), "".to_string()), ("a_b".to_string(), "".to_string())];
+        let archive = export(&documents);
+        let names = String::from_utf8_lossy(&archive);
+        assert!(names.contains("a_b.txt") && names.contains("a_b (2).txt"));
+    }
+}
@@ -0,0 +1,293 @@
+#[macro_use]
+extern crate failure;
+extern crate taskigt;
+
+use failure::Error;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use taskigt::diff;
+use taskigt::formats::Format;
+use taskigt::html::{self, Stylesheet};
+use taskigt::json;
+use taskigt::merge;
+use taskigt::search;
+use taskigt::itemtree::{Item, ItemId, ItemKind, ItemTree, TextMode};
+
+const USAGE: &'static str = r#"Usage: taskigt-cli <command> <file> [args]
+
+Commands:
+  ls <file> [--kind <bullet>]           list items with their paths
+  cat <file>                            print the document, well formatted
+  fmt <file>                            rewrite the document, well formatted
+  add <file> [--parent <path>] [--kind <bullet>] <text...>
+                                        add an item last among the children of <path>
+  set-kind <file> <path> <bullet>       change the kind of an item
+  done <file> <path>                    mark an item as done
+  diff <file> <new file>                show the changes from <file> to <new file>
+                                        (+ added, - removed, ~ changed, > moved)
+  merge <file> <base file> <other file> apply the changes from <base file> to <other file>
+                                        on <file>, conflicts are marked by '! Merge conflict'
+  convert <file> <output file> [--flat] convert between formats, given by the file extensions:
+                                        .md (Markdown), .opml (OPML), .org (Org), .json (JSON),
+                                        others are Taskigt documents. JSON is written with
+                                        nested items, or as node and parent lists with --flat
+  html <file> <output file> [--stylesheet <url>]
+                                        write a read-only web page of the document, with the
+                                        styles included, or linked to <url> (e.g. app.css)
+
+Paths are dot separated child positions, starting at 1 (e.g. 2.1).
+The root of the document is the empty path, only accepted as the parent of `add`.
+"#;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Error> {
+    if args.len() < 2 {
+        print!("{}", USAGE);
+        return Ok(());
+    }
+    let command = &args[0];
+    let file = &args[1];
+    let rest = &args[2..];
+
+    if command == "convert" {
+        let flat = rest.iter().any(|arg| arg == "--flat");
+        let rest: Vec<&String> = rest.iter().filter(|arg| *arg != "--flat").collect();
+        ensure!(rest.len() == 1, "expected <output file>");
+        let content = fs::read_to_string(file)
+            .map_err(|e| format_err!("could not read {}: {}", file, e))?;
+        let tree = Format::of_file(file).read(&title_of(file), &content)?;
+        let output = match Format::of_file(rest[0]) {
+            Format::Json if flat => json::to_flat_json(&tree),
+            format => format.write(&tree)
+        };
+        fs::write(rest[0], output)
+            .map_err(|e| format_err!("could not write {}: {}", rest[0], e))?;
+        return Ok(());
+    }
+
+    if command == "html" {
+        let stylesheet = match flag_value(rest, "--stylesheet") {
+            Some(url) => Stylesheet::Link(url),
+            None => Stylesheet::Inline
+        };
+        let rest = positional(rest, &["--stylesheet"]);
+        ensure!(rest.len() == 1, "expected <output file>");
+        let content = fs::read_to_string(file)
+            .map_err(|e| format_err!("could not read {}: {}", file, e))?;
+        let tree = Format::of_file(file).read(&title_of(file), &content)?;
+        fs::write(rest[0], html::to_html_with(&tree, &stylesheet))
+            .map_err(|e| format_err!("could not write {}: {}", rest[0], e))?;
+        return Ok(());
+    }
+
+    let mut tree = read_tree(file)?;
+    match command.as_ref() {
+        "ls" => {
+            let kind = match flag_value(rest, "--kind") {
+                Some(bullet) => Some(parse_kind(&bullet)?),
+                None => None
+            };
+            list(&tree, tree.root(), kind.as_ref());
+        },
+        "diff" => {
+            ensure!(rest.len() == 1, "expected <new file>");
+            let new_tree = read_tree(&rest[0])?;
+            let changes = diff::diff(&tree, &new_tree);
+            for line in diff::annotate(&tree, &new_tree, &changes) {
+                println!("{}", line);
+            }
+        },
+        "merge" => {
+            ensure!(rest.len() == 2, "expected <base file> <other file>");
+            let base = read_tree(&rest[0])?;
+            let other = read_tree(&rest[1])?;
+            let merged = merge::merge(&base, &tree, &other);
+            write_tree(file, &merged.tree, TextMode::PreserveOriginal)?;
+            if !merged.conflicts.is_empty() {
+                bail!("{} conflict(s), see the '{}' items", merged.conflicts.len(), merge::CONFLICT_TEXT);
+            }
+        },
+        "cat" => {
+            print!("{}", tree.to_text());
+        },
+        "fmt" => {
+            write_tree(file, &tree, TextMode::Normalized)?;
+        },
+        "add" => {
+            let parent = match flag_value(rest, "--parent") {
+                Some(path) => resolve(&tree, &path)?,
+                None => tree.root()
+            };
+            let kind = match flag_value(rest, "--kind") {
+                Some(bullet) => parse_kind(&bullet)?,
+                None => ItemKind::Info
+            };
+            let text = positional(rest, &["--parent", "--kind"]).join(" ");
+            ensure!(!text.is_empty(), "no item text given");
+            let id = tree.add_child(parent, Item::leaf(kind, &text));
+            write_tree(file, &tree, TextMode::PreserveOriginal)?;
+            println!("{}", format_path(&tree.path_of(id)));
+        },
+        "set-kind" => {
+            ensure!(rest.len() == 2, "expected <path> <bullet>");
+            let id = resolve_item(&tree, &rest[0])?;
+            tree.nodes[id].kind = parse_kind(&rest[1])?;
+            write_tree(file, &tree, TextMode::PreserveOriginal)?;
+        },
+        "done" => {
+            ensure!(rest.len() == 1, "expected <path>");
+            let id = resolve_item(&tree, &rest[0])?;
+            tree.nodes[id].kind = ItemKind::Done;
+            write_tree(file, &tree, TextMode::PreserveOriginal)?;
+        },
+        _ => bail!(format!("unknown command '{}'\n\n{}", command, USAGE))
+    }
+    Ok(())
+}
+
+fn title_of(file: &str) -> String {
+    Path::new(file).file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| file.to_string())
+}
+
+fn read_tree(file: &str) -> Result<ItemTree, Error> {
+    let content = fs::read_to_string(file)
+        .map_err(|e| format_err!("could not read {}: {}", file, e))?;
+    Ok(ItemTree::parse(&title_of(file), &content))
+}
+
+/// Edits keep the formatting of untouched lines, only `fmt` normalizes the whole document
+fn write_tree(file: &str, tree: &ItemTree, mode: TextMode) -> Result<(), Error> {
+    fs::write(file, tree.to_text_with(mode))
+        .map_err(|e| format_err!("could not write {}: {}", file, e))?;
+    Ok(())
+}
+
+fn parse_kind(bullet: &str) -> Result<ItemKind, Error> {
+    let (kind, rest) = ItemKind::parse(bullet);
+    ensure!(rest.is_empty() && !bullet.is_empty(),
+            format!("unknown bullet '{}', expected one of - * ? # ! |", bullet));
+    Ok(kind)
+}
+
+/// Converts a user facing path ("2.1", 1-indexed) into a node id
+fn resolve(tree: &ItemTree, path: &str) -> Result<ItemId, Error> {
+    let mut positions = vec![];
+    for part in path.split('.').filter(|part| !part.is_empty()) {
+        let pos: usize = part.parse()
+            .map_err(|_| format_err!("invalid path '{}'", path))?;
+        ensure!(pos > 0, format!("invalid path '{}', positions start at 1", path));
+        positions.push(pos - 1);
+    }
+    tree.node_at(&positions)
+        .ok_or_else(|| format_err!("no item at path '{}'", path))
+}
+
+/// Like `resolve`, but an empty path (the document itself, whose text is its title) is rejected
+fn resolve_item(tree: &ItemTree, path: &str) -> Result<ItemId, Error> {
+    let id = resolve(tree, path)?;
+    ensure!(id != tree.root(), format!("invalid path '{}', expected the path of an item", path));
+    Ok(id)
+}
+
+fn format_path(path: &[usize]) -> String {
+    path.iter()
+        .map(|pos| (pos + 1).to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn list(tree: &ItemTree, node: ItemId, kind: Option<&ItemKind>) {
+    for child_id in &tree.nodes[node].children_ids {
+        let child = &tree.nodes[*child_id];
+        let is_blank = child.kind == ItemKind::Blank || child.kind == ItemKind::BlankLine;
+        let matches = kind.map(|k| search::same_kind(k, &child.kind)).unwrap_or(!is_blank);
+        if matches {
+            let display = child.display();
+            let mut lines = display.split('\n');
+            println!("{:<8} {}", format_path(&tree.path_of(*child_id)), lines.next().unwrap_or(""));
+            // the following lines of verbatim blocks
+            for line in lines {
+                println!("{:<8}   {}", "", line);
+            }
+        }
+        list(tree, *child_id, kind);
+    }
+}
+
+fn flag_value(args: &[String], flag: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|idx| args.get(idx + 1))
+        .cloned()
+}
+
+/// Arguments that are neither one of the given flags nor their values
+fn positional<'a>(args: &'a [String], flags: &[&str]) -> Vec<&'a str> {
+    let mut out = vec![];
+    let mut skip_next = false;
+    for arg in args {
+        if skip_next {
+            skip_next = false;
+        } else if flags.contains(&arg.as_ref()) {
+            skip_next = true;
+        } else {
+            out.push(arg.as_ref());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn paths() {
+        let tree = ItemTree::parse("notes", "- a\n  - b\n- c\n");
+        assert_eq!(tree.root(), resolve(&tree, "").unwrap());
+        for path in &["1", "1.1", "2"] {
+            let id = resolve(&tree, path).unwrap();
+            assert_eq!(*path, format_path(&tree.path_of(id)));
+        }
+        assert_eq!("b", tree.nodes[resolve_item(&tree, "1.1").unwrap()].text);
+        assert_eq!("", format_path(&[]));
+
+        for path in &["0", "x", "1.x", "3", "1.2", "-1"] {
+            assert!(resolve(&tree, path).is_err(), "{}", path);
+        }
+        assert!(resolve_item(&tree, "").is_err(), "the root is not an item");
+    }
+
+    #[test]
+    fn flags() {
+        let rest = args(&["--parent", "2", "write", "--kind", "?", "the report"]);
+        assert_eq!(Some("2".to_string()), flag_value(&rest, "--parent"));
+        assert_eq!(Some("?".to_string()), flag_value(&rest, "--kind"));
+        assert_eq!(None, flag_value(&rest, "--flat"));
+        assert_eq!(None, flag_value(&args(&["--kind"]), "--kind"), "no value after the flag");
+        assert_eq!(vec!["write", "the report"], positional(&rest, &["--parent", "--kind"]));
+        assert_eq!(vec!["--parent", "2"], positional(&args(&["--parent", "2"]), &[]));
+    }
+
+    #[test]
+    fn kinds() {
+        assert_eq!(ItemKind::Planned, parse_kind("?").unwrap());
+        assert!(parse_kind("").is_err());
+        assert!(parse_kind("x").is_err());
+    }
+}
@@ -0,0 +1,267 @@
+extern crate failure;
+extern crate serde_json;
+extern crate taskigt;
+
+use failure::Error;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::time::Duration;
+use taskigt::storage::{DocumentStorage, FileStorage};
+use taskigt::sync::{self, RemoteDocument};
+
+const USAGE: &'static str = r#"Usage: taskigt-server [--port <port>] [--dir <directory>]
+
+A document server for syncing, storing each document as a text file in <directory>
+(default ./taskigt-documents), listening on localhost:<port> (default 8090).
+
+  GET    /documents          list of {title, etag} as json
+  GET    /documents/<title>  the document, with its ETag
+  PUT    /documents/<title>  store the document, if If-Match is its current ETag
+                             (or If-None-Match is *, and there is no such document)
+  DELETE /documents/<title>  remove the document, if If-Match is its current ETag
+
+Documents over 10 MiB are refused, and idle connections are closed after 10 seconds.
+"#;
+
+/// Larger documents are refused, rather than trusting any Content-Length
+const MAX_BODY_BYTES: usize = 10 * 1024 * 1024;
+/// For the request line and headers
+const MAX_HEAD_BYTES: u64 = 64 * 1024;
+/// Connections are handled one at a time, so an idle client can't hold up the others for longer
+const TIMEOUT_SECS: u64 = 10;
+
+struct Request {
+    method: String,
+    path: String,
+    /// Lower case names
+    headers: HashMap<String, String>,
+    body: String
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String
+}
+
+impl Response {
+    fn new(status: u16, body: &str) -> Response {
+        Response { status, headers: vec![], body: body.to_string() }
+    }
+
+    fn with_etag(mut self, content: &str) -> Response {
+        self.headers.push(("ETag", sync::etag(content)));
+        self
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        print!("{}", USAGE);
+        return;
+    }
+    let port = flag_value(&args, "--port").unwrap_or_else(|| "8090".to_string());
+    let dir = PathBuf::from(flag_value(&args, "--dir").unwrap_or_else(|| "taskigt-documents".to_string()));
+    if let Err(e) = serve(&port, dir) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Handles one connection at a time, so checking and storing a version can't race
+fn serve(port: &str, dir: PathBuf) -> io::Result<()> {
+    fs::create_dir_all(&dir)?;
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))?;
+    println!("serving {} on http://localhost:{}", dir.display(), port);
+    let mut storage = FileStorage::new(dir);
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| handle_connection(stream, &mut storage));
+        if let Err(e) = result {
+            eprintln!("connection failed: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, storage: &mut FileStorage) -> io::Result<()> {
+    let timeout = Some(Duration::from_secs(TIMEOUT_SECS));
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader)? {
+        Ok(request) => {
+            let response = handle(&request, storage).unwrap_or_else(|e| Response::new(500, &e.to_string()));
+            println!("{} {} -> {}", request.method, request.path, response.status);
+            response
+        },
+        Err(response) => response
+    };
+    write_response(stream, &response)
+}
+
+/// The request, or the response refusing it
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Result<Request, Response>> {
+    let malformed = || Response::new(400, "malformed request");
+    let mut head = reader.by_ref().take(MAX_HEAD_BYTES);
+    let mut line = String::new();
+    head.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Ok(Err(malformed()))
+    };
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if head.read_line(&mut line)? == 0 {
+            // the head ended early, or was too long
+            return Ok(Err(malformed()));
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some(colon) = line.find(':') {
+            headers.insert(line[..colon].trim().to_lowercase(), line[colon + 1..].trim().to_string());
+        }
+    }
+
+    let length = match headers.get("content-length").map(|length| length.parse::<usize>()) {
+        None => 0,
+        Some(Ok(length)) => length,
+        Some(Err(_)) => return Ok(Err(malformed()))
+    };
+    if length > MAX_BODY_BYTES {
+        return Ok(Err(Response::new(413, &format!("documents are limited to {} bytes", MAX_BODY_BYTES))));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    match String::from_utf8(body) {
+        Ok(body) => Ok(Ok(Request { method, path, headers, body })),
+        Err(_) => Ok(Err(malformed()))
+    }
+}
+
+fn write_response(mut stream: TcpStream, response: &Response) -> io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        412 => "Precondition Failed",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error"
+    };
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, reason);
+    // the web app is served from elsewhere
+    head.push_str("Access-Control-Allow-Origin: *\r\n");
+    head.push_str("Access-Control-Allow-Methods: GET, PUT, DELETE, OPTIONS\r\n");
+    head.push_str("Access-Control-Allow-Headers: Content-Type, If-Match, If-None-Match\r\n");
+    head.push_str("Access-Control-Expose-Headers: ETag\r\n");
+    for &(name, ref value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", response.body.len()));
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(response.body.as_bytes())?;
+    stream.flush()
+}
+
+fn handle(request: &Request, storage: &mut FileStorage) -> Result<Response, Error> {
+    if request.method == "OPTIONS" {
+        return Ok(Response::new(204, ""));
+    }
+    if request.path == "/documents" {
+        return match request.method.as_ref() {
+            "GET" => list(storage),
+            _ => Ok(Response::new(405, ""))
+        };
+    }
+    let prefix = "/documents/";
+    let title = if request.path.starts_with(prefix) {
+        sync::decode_title(&request.path[prefix.len()..])
+    } else {
+        None
+    };
+    let title = match title {
+        Some(ref title) if !title.is_empty() => title,
+        _ => return Ok(Response::new(404, ""))
+    };
+
+    let current = if storage.contains(title) { Some(storage.restore(title)?) } else { None };
+    let if_match = request.headers.get("if-match");
+    let if_none_match = request.headers.get("if-none-match");
+    let precondition_holds = match current {
+        Some(ref content) => if_match.map_or(true, |etag| etag == "*" || *etag == sync::etag(content)) &&
+            if_none_match.is_none(),
+        None => if_match.is_none()
+    };
+
+    match (request.method.as_ref(), current) {
+        ("GET", Some(content)) => Ok(Response::new(200, &content).with_etag(&content)),
+        ("GET", None) | ("DELETE", None) => Ok(Response::new(404, "")),
+        (_, Some(content)) if !precondition_holds => {
+            // the current version, to merge with
+            Ok(Response::new(412, &content).with_etag(&content))
+        },
+        (_, None) if !precondition_holds => Ok(Response::new(412, "")),
+        ("PUT", current) => {
+            storage.save(title, request.body.clone())?;
+            let status = if current.is_some() { 200 } else { 201 };
+            Ok(Response::new(status, "").with_etag(&request.body))
+        },
+        ("DELETE", Some(_)) => {
+            storage.delete(title)?;
+            Ok(Response::new(204, ""))
+        },
+        _ => Ok(Response::new(405, ""))
+    }
+}
+
+fn list(storage: &FileStorage) -> Result<Response, Error> {
+    let mut documents = vec![];
+    for doc in storage.list() {
+        let content = storage.restore(&doc.title)?;
+        documents.push(RemoteDocument { etag: sync::etag(&content), title: doc.title });
+    }
+    documents.sort_by(|a, b| a.title.cmp(&b.title));
+    Ok(Response::new(200, &serde_json::to_string(&documents)?))
+}
+
+fn flag_value(args: &[String], flag: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|pos| args.get(pos + 1))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn read(request: &str) -> Result<Request, Response> {
+        read_request(&mut Cursor::new(request.as_bytes().to_vec())).unwrap()
+    }
+
+    #[test]
+    fn requests() {
+        let request = read("PUT /documents/a HTTP/1.1\r\nContent-Length: 5\r\nIf-Match: \"x\"\r\n\r\n- abc").ok().unwrap();
+        assert_eq!(("PUT", "/documents/a", "- abc"), (request.method.as_str(), request.path.as_str(), request.body.as_str()));
+        assert_eq!(Some(&"\"x\"".to_string()), request.headers.get("if-match"));
+
+        let huge = format!("PUT /documents/a HTTP/1.1\r\nContent-Length: {}\r\n\r\n", usize::max_value());
+        assert_eq!(413, read(&huge).err().unwrap().status);
+        assert_eq!(400, read("PUT /documents/a HTTP/1.1\r\nContent-Length: -1\r\n\r\n").err().unwrap().status);
+        assert_eq!(400, read("GET\r\n\r\n").err().unwrap().status);
+        let endless = format!("GET /documents HTTP/1.1\r\nX: {}", "x".repeat(MAX_HEAD_BYTES as usize));
+        assert_eq!(400, read(&endless).err().unwrap().status);
+    }
+}
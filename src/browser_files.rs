@@ -0,0 +1,52 @@
+use yew::callback::Callback;
+use stdweb::web::TypedArray;
+
+/// Inputs with this class pick files to import
+pub const IMPORT_CLASS: &'static str = "file-import";
+
+/// Sends (file name, content) for each text file picked in an import input
+/// (see `IMPORT_CLASS`) or dropped anywhere on the page.
+///
+/// There are no file or drop events in the html macro, so the listeners are
+/// added to the document, once.
+pub fn watch_imports(callback: Callback<(String, String)>) {
+    let loaded = move |name: String, content: String| callback.emit((name, content));
+    js! { @(no_return)
+        var loaded = @{loaded};
+        var read = function(files) {
+            Array.prototype.forEach.call(files, function(file) {
+                var reader = new FileReader();
+                reader.onload = function() { loaded(file.name, reader.result); };
+                reader.readAsText(file);
+            });
+        };
+        document.addEventListener("change", function(event) {
+            if (event.target.classList && event.target.classList.contains(@{IMPORT_CLASS})) {
+                read(event.target.files);
+                // picking the same file again is a change
+                event.target.value = "";
+            }
+        }, true);
+        document.addEventListener("dragover", function(event) {
+            event.preventDefault();
+        });
+        document.addEventListener("drop", function(event) {
+            event.preventDefault();
+            read(event.dataTransfer.files);
+        });
+    }
+}
+
+/// Lets the browser save the content as a file
+pub fn download(file_name: &str, content: &[u8], mime_type: &str) {
+    let bytes = TypedArray::<u8>::from(content);
+    js! { @(no_return)
+        var link = document.createElement("a");
+        link.href = URL.createObjectURL(new Blob([@{bytes}], { type: @{mime_type} }));
+        link.download = @{file_name};
+        document.body.appendChild(link);
+        link.click();
+        document.body.removeChild(link);
+        setTimeout(function() { URL.revokeObjectURL(link.href); }, 0);
+    }
+}
@@ -0,0 +1,368 @@
+use stdweb::web::{window, Date, Storage};
+use stdweb::Value;
+use serde_json;
+use library::{DocumentIndex, DocumentInfo};
+use storage::{DocumentStorage, MemoryStorage, StorageError, document_info};
+use revisions::{self, Revision, Revisions};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+const BASE_KEY: &'static str = "taskigt.storage";
+
+/// Keeps documents in the browser's localStorage (kept until cleared) or
+/// sessionStorage (cleared when the tab is closed)
+pub struct WebStorage {
+    storage: Storage,
+    index: DocumentIndex
+}
+
+impl WebStorage {
+    pub fn local() -> Self {
+        WebStorage::new(window().local_storage())
+    }
+
+    pub fn session() -> Self {
+        WebStorage::new(window().session_storage())
+    }
+
+    fn new(storage: Storage) -> Self {
+        let index = storage.get(&index_key())
+            .and_then(|index| serde_json::from_str(&index).ok());
+        let mut web_storage = WebStorage { storage, index: DocumentIndex::default() };
+        match index {
+            Some(index) => web_storage.index = index,
+            None => {
+                web_storage.index = web_storage.rebuild_index();
+                // a full storage is reported on the next save
+                let _ = web_storage.store_index();
+            }
+        }
+        web_storage
+    }
+
+    fn store_index(&mut self) -> Result<(), StorageError> {
+        let index = serde_json::to_string(&self.index)
+            .map_err(|e| StorageError::Unavailable(e.to_string()))?;
+        let key = index_key();
+        self.insert(&key, &index, "the document index")
+    }
+
+    /// Finds documents stored before there was an index
+    fn rebuild_index(&self) -> DocumentIndex {
+        let mut index = DocumentIndex::default();
+        let prefix = document_key("");
+        let titles: Vec<String> = (0..self.storage.len())
+            .filter_map(|nth| self.storage.key(nth))
+            .filter(|key| key.starts_with(&prefix))
+            .map(|key| key[prefix.len()..].to_string())
+            .collect();
+        for title in titles {
+            if let Ok(content) = self.restore(&title) {
+                index.update(document_info(&title, &content, 0.0));
+            }
+        }
+        index
+    }
+
+    fn stored_revisions(&self, title: &str) -> Revisions {
+        self.storage.get(&revisions_key(title))
+            .and_then(|revisions| serde_json::from_str(&revisions).ok())
+            .unwrap_or_default()
+    }
+
+    fn store_revisions(&self, title: &str, revisions: &Revisions) -> Result<(), StorageError> {
+        if revisions.is_empty() {
+            self.storage.remove(&revisions_key(title));
+            return Ok(());
+        }
+        let json = serde_json::to_string(revisions)
+            .map_err(|e| StorageError::Unavailable(e.to_string()))?;
+        self.insert(&revisions_key(title), &json, title)
+    }
+
+    /// Makes room by removing the oldest revision of any document, where `revisions`
+    /// are those of the document being saved. False if there are none left.
+    fn prune_oldest_revision(&self, title: &str, revisions: &mut Revisions) -> bool {
+        let others: Vec<(String, Revisions)> = self.index.documents.iter()
+            .filter(|doc| doc.title != title)
+            .map(|doc| (doc.title.clone(), self.stored_revisions(&doc.title)))
+            .collect();
+        let oldest = revisions::oldest_of(others.iter()
+            .map(|&(ref other, ref revisions)| (other.as_str(), revisions))
+            .chain(Some((title, &*revisions))))
+            .map(|oldest| oldest.to_string());
+        match oldest {
+            None => false,
+            Some(ref oldest) if oldest == title => revisions.prune_oldest().is_some(),
+            Some(ref oldest) => {
+                let mut pruned = self.stored_revisions(oldest);
+                pruned.prune_oldest();
+                // smaller than before, so it fits
+                let _ = self.store_revisions(oldest, &pruned);
+                true
+            }
+        }
+    }
+
+    /// Unlike `Storage::insert`, tells a full storage apart from other failures
+    fn insert(&self, key: &str, value: &str, title: &str) -> Result<(), StorageError> {
+        let failure = js! {
+            try {
+                @{&self.storage}.setItem(@{key}, @{value});
+                return null;
+            } catch (error) {
+                var full = error.name === "QuotaExceededError" ||
+                    error.name === "NS_ERROR_DOM_QUOTA_REACHED" || error.code === 22;
+                return full || String(error);
+            }
+        };
+        match failure {
+            Value::Null => Ok(()),
+            Value::String(message) => Err(StorageError::Unavailable(message)),
+            _ => Err(StorageError::QuotaExceeded(title.to_string()))
+        }
+    }
+}
+
+impl DocumentStorage for WebStorage {
+    /// Keeps the replaced version as a revision, pruning the oldest revisions
+    /// while the storage is full
+    fn save(&mut self, title: &str, content: String) -> Result<(), StorageError> {
+        let mut revisions = self.stored_revisions(title);
+        let replaced = self.index.get(title).map(|info| info.modified)
+            .and_then(|saved| self.restore(title).ok().map(|content| Revision { saved, content }));
+        if let Some(replaced) = replaced {
+            revisions.keep(replaced);
+        }
+        loop {
+            let stored = self.store_revisions(title, &revisions)
+                .and_then(|_| self.insert(&document_key(title), &content, title));
+            match stored {
+                Err(StorageError::QuotaExceeded(_)) if self.prune_oldest_revision(title, &mut revisions) => {},
+                Err(e) => return Err(e),
+                Ok(()) => break
+            }
+        }
+        self.index.update(document_info(title, &content, Date::now()));
+        self.store_index()
+    }
+
+    fn restore(&self, title: &str) -> Result<String, StorageError> {
+        self.storage.get(&document_key(title))
+            .ok_or_else(|| StorageError::NotFound(title.to_string()))
+    }
+
+    fn list(&self) -> Vec<DocumentInfo> {
+        self.index.by_modified().into_iter().cloned().collect()
+    }
+
+    fn contains(&self, title: &str) -> bool {
+        self.index.contains(title)
+    }
+
+    fn delete(&mut self, title: &str) -> Result<(), StorageError> {
+        self.storage.remove(&document_key(title));
+        self.storage.remove(&revisions_key(title));
+        self.index.remove(title);
+        self.store_index()
+    }
+
+    fn revisions(&self, title: &str) -> Vec<Revision> {
+        self.stored_revisions(title).newest_first()
+    }
+
+    fn rename(&mut self, title: &str, new_title: &str) -> Result<(), StorageError> {
+        if self.contains(new_title) {
+            return Err(StorageError::AlreadyExists(new_title.to_string()));
+        }
+        let content = self.restore(title)?;
+        let revisions = self.stored_revisions(title);
+        let stored = self.insert(&document_key(new_title), &content, new_title)
+            .and_then(|_| self.store_revisions(new_title, &revisions));
+        if let Err(e) = stored {
+            // the document is left as it was
+            self.storage.remove(&document_key(new_title));
+            self.storage.remove(&revisions_key(new_title));
+            return Err(e);
+        }
+        self.storage.remove(&document_key(title));
+        self.storage.remove(&revisions_key(title));
+        if let Some(mut info) = self.index.remove(title) {
+            info.title = new_title.to_string();
+            self.index.update(info);
+        }
+        self.store_index()
+    }
+}
+
+fn document_key(title: &str) -> String {
+    let mut key = BASE_KEY.to_string();
+    key.push('.');
+    key.push_str(title);
+    key
+}
+
+/// Can't collide with a document key, since those have a '.' after the base key
+fn index_key() -> String {
+    format!("{}:index", BASE_KEY)
+}
+
+fn revisions_key(title: &str) -> String {
+    format!("{}:revisions.{}", BASE_KEY, title)
+}
+
+const DATABASE: &'static str = "taskigt";
+const STORE: &'static str = "documents";
+
+/// Keeps documents in IndexedDB, which allows far more data than localStorage.
+///
+/// IndexedDB can only be used asynchronously, so documents are kept in memory and
+/// written through to the database. Documents from earlier sessions show up once
+/// the database has been read, shortly after start. A document saved before its
+/// stored version was read keeps that version among its revisions.
+pub struct IndexedDbStorage {
+    cache: Rc<RefCell<Cache>>,
+    /// A promise of the opened database
+    database: Value
+}
+
+#[derive(Default)]
+struct Cache {
+    documents: MemoryStorage,
+    /// Until the database has been read
+    loading: bool,
+    /// Titles deleted or renamed while loading, which are not to be loaded
+    removed: HashSet<String>
+}
+
+impl IndexedDbStorage {
+    pub fn open() -> Self {
+        let cache = Rc::new(RefCell::new(Cache { loading: true, ..Cache::default() }));
+        let loaded_cache = cache.clone();
+        let loaded = move |title: String, content: String, modified: f64, revisions: String| -> bool {
+            let mut cache = loaded_cache.borrow_mut();
+            if cache.removed.contains(&title) {
+                return false;
+            }
+            let revisions = serde_json::from_str(&revisions).unwrap_or_default();
+            cache.documents.load(&title, content, modified, revisions)
+        };
+        let finished_cache = cache.clone();
+        let finished = move |database: Value, merged: Vec<String>| {
+            let mut cache = finished_cache.borrow_mut();
+            cache.loading = false;
+            cache.removed.clear();
+            // the stored versions are now revisions
+            for title in merged {
+                put(&database, &cache.documents, &title);
+            }
+        };
+        let database = js! {
+            var loaded = @{loaded};
+            var finished = @{finished};
+            var database = new Promise(function(resolve, reject) {
+                var request = indexedDB.open(@{DATABASE}, 1);
+                request.onupgradeneeded = function() {
+                    request.result.createObjectStore(@{STORE}, { keyPath: "title" });
+                };
+                request.onsuccess = function() { resolve(request.result); };
+                request.onerror = function() { reject(request.error); };
+            });
+            database.then(function(db) {
+                var documents = db.transaction(@{STORE}, "readonly").objectStore(@{STORE});
+                var merged = [];
+                documents.openCursor().onsuccess = function(event) {
+                    var cursor = event.target.result;
+                    if (cursor) {
+                        var record = cursor.value;
+                        if (loaded(record.title, record.content, record.modified, record.revisions || "{}")) {
+                            merged.push(record.title);
+                        }
+                        cursor.continue();
+                    } else {
+                        finished(database, merged);
+                        loaded.drop();
+                        finished.drop();
+                    }
+                };
+            }).catch(function(error) {
+                console.log("failed to open the document database", error);
+            });
+            return database;
+        };
+        IndexedDbStorage { cache, database }
+    }
+
+    fn remove(&self, title: &str) {
+        let mut cache = self.cache.borrow_mut();
+        if cache.loading {
+            cache.removed.insert(title.to_string());
+        }
+        js! { @(no_return)
+            var title = @{title};
+            @{&self.database}.then(function(db) {
+                db.transaction(@{STORE}, "readwrite").objectStore(@{STORE}).delete(title);
+            });
+        }
+    }
+}
+
+/// Writes the document, as cached, to the database. The write waits for the
+/// database to be read, as that began first.
+fn put(database: &Value, documents: &MemoryStorage, title: &str) {
+    let (content, modified) = match (documents.restore(title), documents.list().into_iter().find(|doc| doc.title == title)) {
+        (Ok(content), Some(info)) => (content, info.modified),
+        _ => return
+    };
+    let revisions = documents.revisions_of(title)
+        .and_then(|revisions| serde_json::to_string(revisions).ok())
+        .unwrap_or_else(|| "{}".to_string());
+    js! { @(no_return)
+        var document = {
+            title: @{title}, content: @{&content}, modified: @{modified}, revisions: @{revisions}
+        };
+        @{database}.then(function(db) {
+            var transaction = db.transaction(@{STORE}, "readwrite");
+            transaction.objectStore(@{STORE}).put(document);
+            transaction.onerror = function() {
+                console.log("failed to store " + document.title, transaction.error);
+            };
+        });
+    }
+}
+
+impl DocumentStorage for IndexedDbStorage {
+    fn save(&mut self, title: &str, content: String) -> Result<(), StorageError> {
+        let mut cache = self.cache.borrow_mut();
+        cache.removed.remove(title);
+        cache.documents.save_at(title, content, Date::now());
+        put(&self.database, &cache.documents, title);
+        Ok(())
+    }
+
+    fn restore(&self, title: &str) -> Result<String, StorageError> {
+        self.cache.borrow().documents.restore(title)
+    }
+
+    fn list(&self) -> Vec<DocumentInfo> {
+        self.cache.borrow().documents.list()
+    }
+
+    fn revisions(&self, title: &str) -> Vec<Revision> {
+        self.cache.borrow().documents.revisions(title)
+    }
+
+    fn delete(&mut self, title: &str) -> Result<(), StorageError> {
+        self.remove(title);
+        self.cache.borrow_mut().documents.delete(title)
+    }
+
+    fn rename(&mut self, title: &str, new_title: &str) -> Result<(), StorageError> {
+        self.cache.borrow_mut().documents.rename(title, new_title)?;
+        self.cache.borrow_mut().removed.remove(new_title);
+        put(&self.database, &self.cache.borrow().documents, new_title);
+        self.remove(title);
+        Ok(())
+    }
+}
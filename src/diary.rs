@@ -0,0 +1,186 @@
+use itemtree::{ItemTree, ItemId, ItemKind, Item};
+use itemtree::ItemKind::*;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Appended to the text of tasks that have been migrated to a later day
+pub const MIGRATED_MARKER: &'static str = "(migrated)";
+
+/// A calendar date, diary pages are titled by its ISO 8601 form (e.g. 2018-07-14)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32
+}
+
+impl Date {
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Date> {
+        if month >= 1 && month <= 12 && day >= 1 && day <= days_in_month(year, month) {
+            Some(Date { year, month, day })
+        } else {
+            None
+        }
+    }
+
+    pub fn parse(iso: &str) -> Option<Date> {
+        let parts: Vec<&str> = iso.trim().split('-').collect();
+        if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() != 2 || parts[2].len() != 2 {
+            return None;
+        }
+        Date::new(parts[0].parse().ok()?, parts[1].parse().ok()?, parts[2].parse().ok()?)
+    }
+
+    pub fn title(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn previous(&self) -> Date {
+        if self.day > 1 {
+            Date { day: self.day - 1, ..*self }
+        } else if self.month > 1 {
+            Date { month: self.month - 1, day: days_in_month(self.year, self.month - 1), ..*self }
+        } else {
+            Date { year: self.year - 1, month: 12, day: 31 }
+        }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+pub fn is_unfinished(kind: &ItemKind) -> bool {
+    match *kind {
+        Planned | Doing | Blocked => true,
+        _ => false
+    }
+}
+
+fn is_migrated(item: &Item) -> bool {
+    item.text.ends_with(MIGRATED_MARKER)
+}
+
+/// The unfinished tasks that have not been migrated yet, in document order
+pub fn unmigrated_tasks(tree: &ItemTree) -> Vec<ItemId> {
+    let mut tasks = vec![];
+    collect_unmigrated(tree, tree.root(), &mut tasks);
+    tasks
+}
+
+fn collect_unmigrated(tree: &ItemTree, node: ItemId, tasks: &mut Vec<ItemId>) {
+    for child_id in &tree.nodes[node].children_ids {
+        let child = &tree.nodes[*child_id];
+        if is_unfinished(&child.kind) && !is_migrated(child) {
+            tasks.push(*child_id);
+        }
+        collect_unmigrated(tree, *child_id, tasks);
+    }
+}
+
+/// Finds the most recent diary page before `today` among the stored `titles`, however
+/// long ago, using `load` to look up the content of a page by its title
+pub fn previous_page<'a, I, F>(today: Date, titles: I, mut load: F) -> Option<ItemTree>
+    where I: IntoIterator<Item = &'a str>, F: FnMut(&str) -> Option<String> {
+    let previous = titles.into_iter()
+        .filter_map(|title| Date::parse(title).filter(|date| date.title() == title))
+        .filter(|date| *date < today)
+        .max()?;
+    let title = previous.title();
+    load(&title).map(|content| ItemTree::parse(&title, &content))
+}
+
+/// Copies all unfinished tasks of `previous` into `today`, along with their ancestors
+/// (reusing equal ancestors already in `today`), and marks them as migrated in `previous`.
+/// Children of the tasks are only copied if they are unfinished tasks themselves.
+/// Returns the number of migrated tasks.
+pub fn migrate(previous: &mut ItemTree, today: &mut ItemTree) -> usize {
+    let tasks = unmigrated_tasks(previous);
+    let mut copies = HashMap::new();
+    copies.insert(previous.root(), today.root());
+    for task in &tasks {
+        copy_of(previous, today, *task, &mut copies);
+    }
+    for task in &tasks {
+        let text = &mut previous.nodes[*task].text;
+        text.push(' ');
+        text.push_str(MIGRATED_MARKER);
+    }
+    tasks.len()
+}
+
+fn copy_of(previous: &ItemTree, today: &mut ItemTree, id: ItemId,
+           copies: &mut HashMap<ItemId, ItemId>) -> ItemId {
+    if let Some(copy) = copies.get(&id) {
+        return *copy;
+    }
+    let parent = previous.parent(id)
+        .expect("only the root has no parent");
+    let parent_copy = copy_of(previous, today, parent, copies);
+
+    let item = &previous.nodes[id];
+    let existing = today.nodes[parent_copy].children_ids.iter()
+        .find(|child| {
+            let child = &today.nodes[**child];
+            child.kind == item.kind && child.text == item.text
+        })
+        .cloned();
+    let copy = existing.unwrap_or_else(|| {
+        today.add_child(parent_copy, Item::leaf(item.kind.clone(), &item.text))
+    });
+    copies.insert(id, copy);
+    copy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        let date = Date::parse("2016-03-01").unwrap();
+        assert_eq!("2016-02-29", date.previous().title());
+        assert_eq!("2015-12-31", Date::parse("2016-01-01").unwrap().previous().title());
+        assert_eq!(None, Date::parse("2017-02-29"));
+        assert_eq!(None, Date::parse("My items"));
+    }
+
+    #[test]
+    fn migrate_unfinished() {
+        let mut previous = ItemTree::parse("2018-07-13",
+            "  - Work\n    * Refactor\n      # Extract module\n      ? Add tests\n    # Meeting\n  - Home\n    ! Plumber\n");
+        let mut today = ItemTree::parse("2018-07-14", "  - Home\n    - Buy milk\n");
+
+        assert_eq!(3, migrate(&mut previous, &mut today));
+        assert_eq!("  - Home\n    - Buy milk\n    ! Plumber\n  - Work\n    * Refactor\n      ? Add tests\n",
+                   today.to_text());
+        assert_eq!("  - Work\n    * Refactor (migrated)\n      # Extract module\n      ? Add tests (migrated)\n    # Meeting\n  - Home\n    ! Plumber (migrated)\n",
+                   previous.to_text());
+
+        // nothing left to migrate
+        assert_eq!(0, migrate(&mut previous, &mut today));
+    }
+
+    #[test]
+    fn find_previous_page() {
+        let today = Date::parse("2018-07-14").unwrap();
+        let titles = vec!["2018-07-15", "2017-01-10", "My items", "2018-07-14", " 2018-07-13", "2018-05-10"];
+        let page = previous_page(today, titles.iter().cloned(), |title| {
+            if title == "2018-05-10" { Some("? todo".to_string()) } else { None }
+        });
+        // more than a month ago
+        assert_eq!("2018-05-10", page.unwrap().title());
+        assert!(previous_page(today, vec!["2018-07-14", "Plans"], |_| Some("".to_string())).is_none());
+    }
+}
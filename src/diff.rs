@@ -0,0 +1,489 @@
+use itemtree::{ItemTree, ItemId, ItemKind};
+use itemtree::ItemKind::*;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// How similar two texts must be for an item to count as edited, rather than removed and added
+pub const SIMILARITY_THRESHOLD: f64 = 0.5;
+
+/// A change from the old to the new version of a tree. Ids of the new tree
+/// are called `new`, ids of the old tree `old`. An item that was both moved and
+/// edited has one change of each.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Added { new: ItemId },
+    Removed { old: ItemId },
+    /// Now below another parent, or in another order among its siblings
+    Moved { old: ItemId, new: ItemId },
+    Edited { old: ItemId, new: ItemId },
+    KindChanged { old: ItemId, new: ItemId }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Diff {
+    /// The item of the old tree each item of the new tree corresponds to
+    pub matches: HashMap<ItemId, ItemId>,
+    /// Changes to items of the new tree in document order, followed by removed items
+    pub changes: Vec<Change>
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Compares two versions of a tree. Items are matched below matching parents by
+/// kind and text, then by text, then by text similarity. Remaining items are
+/// matched anywhere in the tree by text, to find moved items, and finally items
+/// with matching children count as edited (e.g. a renamed heading).
+pub fn diff(old: &ItemTree, new: &ItemTree) -> Diff {
+    let mut matcher = Matcher { old, new, new_to_old: HashMap::new(), used: HashSet::new() };
+    matcher.add_match(new.root(), old.root());
+    matcher.match_moved();
+    matcher.match_by_children();
+    let new_to_old = matcher.new_to_old;
+
+    let moved = moved_items(old, new, &new_to_old);
+    let mut changes = vec![];
+    for id in preorder(new) {
+        let old_id = match new_to_old.get(&id) {
+            Some(old_id) => *old_id,
+            None => {
+                changes.push(Change::Added { new: id });
+                continue;
+            }
+        };
+        if moved.contains(&id) {
+            changes.push(Change::Moved { old: old_id, new: id });
+        }
+        if old.nodes[old_id].text != new.nodes[id].text {
+            changes.push(Change::Edited { old: old_id, new: id });
+        }
+        if old.nodes[old_id].kind != new.nodes[id].kind {
+            changes.push(Change::KindChanged { old: old_id, new: id });
+        }
+    }
+    let matched_old: HashSet<ItemId> = new_to_old.values().cloned().collect();
+    for id in preorder(old) {
+        if !matched_old.contains(&id) {
+            changes.push(Change::Removed { old: id });
+        }
+    }
+    Diff { matches: new_to_old, changes }
+}
+
+struct Matcher<'a> {
+    old: &'a ItemTree,
+    new: &'a ItemTree,
+    new_to_old: HashMap<ItemId, ItemId>,
+    /// Matched items of the old tree
+    used: HashSet<ItemId>
+}
+
+impl<'a> Matcher<'a> {
+    fn add_match(&mut self, new_id: ItemId, old_id: ItemId) {
+        self.new_to_old.insert(new_id, old_id);
+        self.used.insert(old_id);
+        self.match_children(new_id, old_id);
+    }
+
+    fn match_children(&mut self, new_parent: ItemId, old_parent: ItemId) {
+        let (old, new) = (self.old, self.new);
+        let new_children = diffed_children(new, new_parent);
+        let old_children = diffed_children(old, old_parent);
+
+        let mut pairs: Vec<(ItemId, ItemId)> = vec![];
+        let exact = |n: ItemId, o: ItemId| old.nodes[o].kind == new.nodes[n].kind &&
+            old.nodes[o].text == new.nodes[n].text;
+        let same_text = |n: ItemId, o: ItemId| old.nodes[o].text == new.nodes[n].text;
+        match_pass(&new_children, &old_children, &self.used, &mut pairs, exact);
+        match_pass(&new_children, &old_children, &self.used, &mut pairs, same_text);
+        for new_id in &new_children {
+            if pairs.iter().any(|&(n, _)| n == *new_id) {
+                continue;
+            }
+            let best = old_children.iter()
+                .filter(|old_id| !pairs.iter().any(|&(_, o)| o == **old_id) && !self.used.contains(*old_id))
+                .map(|old_id| (*old_id, similarity(&old.nodes[*old_id].text, &new.nodes[*new_id].text)))
+                .filter(|&(_, score)| score >= SIMILARITY_THRESHOLD)
+                .fold(None, |best: Option<(ItemId, f64)>, candidate| match best {
+                    Some(best) if best.1 >= candidate.1 => Some(best),
+                    _ => Some(candidate)
+                });
+            if let Some((old_id, _)) = best {
+                pairs.push((*new_id, old_id));
+            }
+        }
+
+        for (new_id, old_id) in pairs {
+            self.add_match(new_id, old_id);
+        }
+    }
+
+    /// Matches the remaining items by text, wherever they are
+    fn match_moved(&mut self) {
+        let old_ids = preorder(self.old);
+        for new_id in preorder(self.new) {
+            if self.new_to_old.contains_key(&new_id) {
+                continue;
+            }
+            let item = &self.new.nodes[new_id];
+            let unmatched: Vec<ItemId> = old_ids.iter()
+                .filter(|old_id| !self.used.contains(*old_id) && self.old.nodes[**old_id].text == item.text)
+                .cloned()
+                .collect();
+            let found = unmatched.iter()
+                .find(|old_id| self.old.nodes[**old_id].kind == item.kind)
+                .or_else(|| unmatched.first())
+                .cloned();
+            if let Some(old_id) = found {
+                self.add_match(new_id, old_id);
+            }
+        }
+    }
+
+    /// Matches the remaining items to unmatched siblings (by the matched parents) sharing
+    /// the most children with them
+    fn match_by_children(&mut self) {
+        for new_id in preorder(self.new) {
+            if self.new_to_old.contains_key(&new_id) {
+                continue;
+            }
+            let old_parent = match self.new.parent(new_id).and_then(|parent| self.new_to_old.get(&parent)) {
+                Some(old_parent) => *old_parent,
+                None => continue
+            };
+            let old_children: HashSet<ItemId> = diffed_children(self.new, new_id).iter()
+                .filter_map(|child| self.new_to_old.get(child))
+                .cloned()
+                .collect();
+            let best = diffed_children(self.old, old_parent).into_iter()
+                .filter(|old_id| !self.used.contains(old_id))
+                .map(|old_id| {
+                    let shared = self.old.nodes[old_id].children_ids.iter()
+                        .filter(|child| old_children.contains(*child))
+                        .count();
+                    (old_id, shared)
+                })
+                .filter(|&(_, shared)| shared > 0)
+                .max_by_key(|&(old_id, shared)| (shared, cmp::Reverse(old_id)));
+            if let Some((old_id, _)) = best {
+                self.new_to_old.insert(new_id, old_id);
+                self.used.insert(old_id);
+            }
+        }
+    }
+}
+
+/// Pairs up unpaired children for which `is_match` holds, first come first served
+fn match_pass<F>(new_children: &[ItemId], old_children: &[ItemId], used: &HashSet<ItemId>,
+                 pairs: &mut Vec<(ItemId, ItemId)>, is_match: F)
+    where F: Fn(ItemId, ItemId) -> bool {
+    for new_id in new_children {
+        if pairs.iter().any(|&(n, _)| n == *new_id) {
+            continue;
+        }
+        let found = old_children.iter()
+            .find(|old_id| !pairs.iter().any(|&(_, o)| o == **old_id) &&
+                !used.contains(*old_id) && is_match(*new_id, **old_id))
+            .cloned();
+        if let Some(old_id) = found {
+            pairs.push((*new_id, old_id));
+        }
+    }
+}
+
+/// Children that take part in the diff, i.e. all but blank lines
+fn diffed_children(tree: &ItemTree, id: ItemId) -> Vec<ItemId> {
+    tree.nodes[id].children_ids.iter()
+        .filter(|child| tree.nodes[**child].kind != BlankLine)
+        .cloned()
+        .collect()
+}
+
+/// The items taking part in the diff, in document order, excluding the root
+fn preorder(tree: &ItemTree) -> Vec<ItemId> {
+    let mut ids = vec![];
+    let mut stack = diffed_children(tree, tree.root());
+    stack.reverse();
+    while let Some(id) = stack.pop() {
+        ids.push(id);
+        let mut children = diffed_children(tree, id);
+        children.reverse();
+        stack.extend(children);
+    }
+    ids
+}
+
+/// Items with another parent than before (including added parents), or that changed order
+/// relative to their siblings. The fewest possible siblings are counted as moved.
+fn moved_items(old: &ItemTree, new: &ItemTree, new_to_old: &HashMap<ItemId, ItemId>) -> HashSet<ItemId> {
+    let mut moved: HashSet<ItemId> = new_to_old.keys()
+        .filter(|id| new.parent(**id).map_or(false, |parent| !new_to_old.contains_key(&parent)))
+        .cloned()
+        .collect();
+    for (new_parent, old_parent) in new_to_old {
+        let old_siblings = &old.nodes[*old_parent].children_ids;
+        let mut stayed = vec![];
+        for new_id in &new.nodes[*new_parent].children_ids {
+            let old_id = match new_to_old.get(new_id) {
+                Some(old_id) => old_id,
+                None => continue
+            };
+            match old_siblings.iter().position(|sibling| sibling == old_id) {
+                Some(pos) => stayed.push((*new_id, pos)),
+                None => { moved.insert(*new_id); }
+            }
+        }
+        let in_order = longest_increasing(&stayed.iter().map(|&(_, pos)| pos).collect::<Vec<_>>());
+        for (idx, &(new_id, _)) in stayed.iter().enumerate() {
+            if !in_order.contains(&idx) {
+                moved.insert(new_id);
+            }
+        }
+    }
+    moved
+}
+
+/// Indices of a longest increasing subsequence
+fn longest_increasing(values: &[usize]) -> HashSet<usize> {
+    // length of the longest subsequence ending at each index, and the index before it
+    let mut lengths: Vec<(usize, Option<usize>)> = vec![];
+    for (idx, value) in values.iter().enumerate() {
+        let best = (0..idx)
+            .filter(|prev| values[*prev] < *value)
+            .max_by_key(|prev| lengths[*prev].0);
+        lengths.push(match best {
+            Some(prev) => (lengths[prev].0 + 1, Some(prev)),
+            None => (1, None)
+        });
+    }
+    let mut indices = HashSet::new();
+    let mut next = (0..values.len()).max_by_key(|idx| lengths[*idx].0);
+    while let Some(idx) = next {
+        indices.insert(idx);
+        next = lengths[idx].1;
+    }
+    indices
+}
+
+/// Dice coefficient of the character pairs of the texts, ignoring case:
+/// 1.0 for equal texts, 0.0 for texts without common pairs
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a = a.to_lowercase();
+    let b = b.to_lowercase();
+    if a == b {
+        return 1.0;
+    }
+    let pairs = |text: &str| -> Vec<(char, char)> {
+        let chars: Vec<char> = text.chars().collect();
+        chars.windows(2).map(|pair| (pair[0], pair[1])).collect()
+    };
+    let a_pairs = pairs(&a);
+    let mut b_pairs = pairs(&b);
+    let total = a_pairs.len() + b_pairs.len();
+    if total == 0 {
+        return 0.0;
+    }
+    let mut common = 0;
+    for pair in a_pairs {
+        if let Some(pos) = b_pairs.iter().position(|other| *other == pair) {
+            b_pairs.swap_remove(pos);
+            common += 1;
+        }
+    }
+    2.0 * common as f64 / total as f64
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LineStatus {
+    Unchanged,
+    Added,
+    Removed,
+    /// Edited or changed kind, with the kind and text of the old version
+    Changed(ItemKind, String)
+}
+
+/// A line of an annotated tree, showing both versions at once
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiffLine {
+    /// Top level items have depth 0
+    pub depth: usize,
+    pub kind: ItemKind,
+    pub text: String,
+    pub status: LineStatus,
+    pub moved: bool
+}
+
+impl fmt::Display for DiffLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = match self.status {
+            LineStatus::Unchanged => ' ',
+            LineStatus::Added => '+',
+            LineStatus::Removed => '-',
+            LineStatus::Changed(..) => '~'
+        };
+        let moved = if self.moved { '>' } else { ' ' };
+        write!(f, "{}{} {:indent$}{} {}", status, moved, "", self.kind.symbol(), self.text,
+               indent = self.depth * 2)?;
+        if let LineStatus::Changed(ref kind, ref text) = self.status {
+            write!(f, "  (was {} {})", kind.symbol(), text)?;
+        }
+        Ok(())
+    }
+}
+
+/// The new tree, with the removed items of the old tree where they were
+pub fn annotate(old: &ItemTree, new: &ItemTree, diff: &Diff) -> Vec<DiffLine> {
+    let moved: HashSet<ItemId> = diff.changes.iter()
+        .filter_map(|change| match *change {
+            Change::Moved { new, .. } => Some(new),
+            _ => None
+        })
+        .collect();
+    let matched_old: HashSet<ItemId> = diff.matches.values().cloned().collect();
+    let annotator = Annotator { old, new, diff, moved, matched_old };
+    let mut lines = vec![];
+    annotator.children(new.root(), 0, &mut lines);
+    lines
+}
+
+struct Annotator<'a> {
+    old: &'a ItemTree,
+    new: &'a ItemTree,
+    diff: &'a Diff,
+    moved: HashSet<ItemId>,
+    matched_old: HashSet<ItemId>
+}
+
+impl<'a> Annotator<'a> {
+    /// Lines for the children of `new_id`, with removed children of its old version
+    /// placed after the closest preceding sibling that is still there
+    fn children(&self, new_id: ItemId, depth: usize, lines: &mut Vec<DiffLine>) {
+        let new_children = diffed_children(self.new, new_id);
+        let mut removed_after: HashMap<Option<ItemId>, Vec<ItemId>> = HashMap::new();
+        if let Some(old_id) = self.diff.matches.get(&new_id) {
+            let mut anchor = None;
+            for old_child in diffed_children(self.old, *old_id) {
+                if self.matched_old.contains(&old_child) {
+                    let still_here = new_children.iter()
+                        .find(|child| self.diff.matches.get(child) == Some(&old_child));
+                    if let Some(child) = still_here {
+                        anchor = Some(*child);
+                    }
+                } else {
+                    removed_after.entry(anchor).or_insert_with(Vec::new).push(old_child);
+                }
+            }
+        }
+
+        let removed_lines = |anchor: Option<ItemId>, lines: &mut Vec<DiffLine>| {
+            if let Some(removed) = removed_after.get(&anchor) {
+                for old_id in removed {
+                    self.removed(*old_id, depth, lines);
+                }
+            }
+        };
+        removed_lines(None, lines);
+        for child in new_children {
+            let item = &self.new.nodes[child];
+            let status = match self.diff.matches.get(&child) {
+                None => LineStatus::Added,
+                Some(old_id) => {
+                    let old_item = &self.old.nodes[*old_id];
+                    if old_item.kind == item.kind && old_item.text == item.text {
+                        LineStatus::Unchanged
+                    } else {
+                        LineStatus::Changed(old_item.kind.clone(), old_item.text.clone())
+                    }
+                }
+            };
+            lines.push(DiffLine {
+                depth,
+                kind: item.kind.clone(),
+                text: item.text.clone(),
+                status,
+                moved: self.moved.contains(&child)
+            });
+            self.children(child, depth + 1, lines);
+            removed_lines(Some(child), lines);
+        }
+    }
+
+    /// Lines for a removed item, and those of its children that were removed too
+    fn removed(&self, old_id: ItemId, depth: usize, lines: &mut Vec<DiffLine>) {
+        let item = &self.old.nodes[old_id];
+        lines.push(DiffLine {
+            depth,
+            kind: item.kind.clone(),
+            text: item.text.clone(),
+            status: LineStatus::Removed,
+            moved: false
+        });
+        for child in diffed_children(self.old, old_id) {
+            if !self.matched_old.contains(&child) {
+                self.removed(child, depth + 1, lines);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(tree: &ItemTree, diff: &Diff) -> Vec<String> {
+        diff.changes.iter().map(|change| match *change {
+            Change::Added { new } => format!("added {}", tree.nodes[new].text),
+            Change::Removed { old } => format!("removed {}", old),
+            Change::Moved { new, .. } => format!("moved {}", tree.nodes[new].text),
+            Change::Edited { new, .. } => format!("edited {}", tree.nodes[new].text),
+            Change::KindChanged { new, .. } => format!("kind of {}", tree.nodes[new].text)
+        }).collect()
+    }
+
+    #[test]
+    fn unchanged() {
+        let old = ItemTree::parse("doc", "  - a\n    ? b\n\n  - c\n");
+        let new = ItemTree::parse("doc", "- a\n  ? b\n- c\n");
+        assert!(diff(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn changes() {
+        let old = ItemTree::parse("doc", "  - Project\n    ? Write the docs\n    ? Review\n  - Old stuff\n    - Notes\n");
+        let new = ItemTree::parse("doc", "  - Project\n    # Write the documentation\n    - Notes\n  - Later\n");
+        let diff = diff(&old, &new);
+        assert_eq!(vec!["edited Write the documentation", "kind of Write the documentation",
+                        "moved Notes", "added Later", "removed 3", "removed 4"],
+                   texts(&new, &diff));
+    }
+
+    #[test]
+    fn reordered() {
+        let old = ItemTree::parse("doc", "  - a\n  - b\n  - c\n  - d\n");
+        let new = ItemTree::parse("doc", "  - a\n  - c\n  - d\n  - b\n");
+        assert_eq!(vec!["moved b"], texts(&new, &diff(&old, &new)));
+    }
+
+    #[test]
+    fn annotated() {
+        let old = ItemTree::parse("doc", "  - a\n    ? gone\n    ? stays\n  - b\n");
+        let new = ItemTree::parse("doc", "  - b\n  - a\n    # stays\n    - new\n");
+        let lines: Vec<String> = annotate(&old, &new, &diff(&old, &new)).iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(vec![" > - b", "   - a", "-    ? gone", "~    # stays  (was ? stays)", "+    - new"],
+                   lines);
+    }
+
+    #[test]
+    fn similar_texts() {
+        assert_eq!(1.0, similarity("Same", "same"));
+        assert!(similarity("Write the docs", "Write the documentation") > SIMILARITY_THRESHOLD);
+        assert!(similarity("Buy milk", "Call plumber") < SIMILARITY_THRESHOLD);
+        assert_eq!(0.0, similarity("", "a"));
+    }
+}
@@ -0,0 +1,101 @@
+use failure::Error;
+use itemtree::{ItemTree, TextMode};
+use markdown;
+use opml;
+use org;
+use json;
+
+/// The file formats documents can be read from and written to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Taskigt,
+    Markdown,
+    Opml,
+    Org,
+    Json
+}
+
+impl Format {
+    pub const ALL: [Format; 5] = [Format::Taskigt, Format::Markdown, Format::Opml, Format::Org, Format::Json];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Format::Taskigt => "Taskigt",
+            Format::Markdown => "Markdown",
+            Format::Opml => "OPML",
+            Format::Org => "Org",
+            Format::Json => "JSON"
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Format::Taskigt => "txt",
+            Format::Markdown => "md",
+            Format::Opml => "opml",
+            Format::Org => "org",
+            Format::Json => "json"
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match *self {
+            Format::Taskigt => "text/plain;charset=utf-8",
+            Format::Markdown => "text/markdown;charset=utf-8",
+            Format::Opml => "text/x-opml;charset=utf-8",
+            Format::Org => "text/x-org;charset=utf-8",
+            Format::Json => "application/json"
+        }
+    }
+
+    /// The format of a file, by its extension. Unknown files are read as Taskigt
+    /// documents, since any text is.
+    pub fn of_file(name: &str) -> Format {
+        let extension = name.rsplit('.').next().unwrap_or("").to_lowercase();
+        match extension.as_ref() {
+            "md" | "markdown" => Format::Markdown,
+            "opml" => Format::Opml,
+            "org" => Format::Org,
+            "json" => Format::Json,
+            _ => Format::Taskigt
+        }
+    }
+
+    pub fn write(&self, tree: &ItemTree) -> String {
+        match *self {
+            Format::Taskigt => tree.to_text_with(TextMode::PreserveOriginal),
+            Format::Markdown => markdown::to_markdown(tree),
+            Format::Opml => opml::to_opml(tree),
+            Format::Org => org::to_org(tree),
+            Format::Json => json::to_json(tree)
+        }
+    }
+
+    pub fn read(&self, title: &str, content: &str) -> Result<ItemTree, Error> {
+        match *self {
+            Format::Taskigt => Ok(ItemTree::parse(title, content)),
+            Format::Markdown => Ok(markdown::from_markdown(title, content)),
+            Format::Opml => opml::from_opml(title, content),
+            Format::Org => Ok(org::from_org(title, content)),
+            Format::Json => json::from_json(title, content)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats() {
+        assert_eq!(Format::Markdown, Format::of_file("notes.MD"));
+        assert_eq!(Format::Taskigt, Format::of_file("notes"));
+        assert_eq!(Format::Opml, Format::of_file("outline.opml"));
+        assert_eq!(Format::Org, Format::of_file("journal.org"));
+        assert_eq!(Format::Json, Format::of_file("backup.json"));
+        let tree = ItemTree::parse("Plans", "- home\n  ? paint\n");
+        for format in Format::ALL.iter() {
+            assert_eq!(tree, format.read("Plans", &format.write(&tree)).unwrap(), "{}", format.name());
+        }
+    }
+}
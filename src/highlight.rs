@@ -0,0 +1,191 @@
+/// The kinds of text told apart when highlighting verbatim blocks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Token {
+    Plain,
+    Keyword,
+    Str,
+    Number,
+    Comment
+}
+
+impl Token {
+    /// The CSS class of the token, see static/app.css
+    pub fn class(&self) -> Option<&'static str> {
+        match *self {
+            Token::Plain => None,
+            Token::Keyword => Some("syntax-keyword"),
+            Token::Str => Some("syntax-string"),
+            Token::Number => Some("syntax-number"),
+            Token::Comment => Some("syntax-comment")
+        }
+    }
+}
+
+struct Language {
+    names: &'static [&'static str],
+    keywords: &'static [&'static str],
+    /// SQL keywords are written in any case
+    case_insensitive: bool,
+    line_comment: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static str
+}
+
+const LANGUAGES: [Language; 5] = [
+    Language {
+        names: &["rust", "rs"],
+        keywords: &["as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn",
+                    "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+                    "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+                    "unsafe", "use", "where", "while"],
+        case_insensitive: false,
+        line_comment: &["//"],
+        block_comment: Some(("/*", "*/")),
+        // not ', which also starts lifetimes
+        quotes: "\""
+    },
+    Language {
+        names: &["sql"],
+        keywords: &["select", "from", "where", "and", "or", "not", "null", "is", "in", "as", "join",
+                    "left", "right", "inner", "outer", "on", "group", "by", "order", "having", "limit",
+                    "insert", "into", "values", "update", "set", "delete", "create", "table", "drop",
+                    "alter", "index", "distinct", "union", "case", "when", "then", "else", "end"],
+        case_insensitive: true,
+        line_comment: &["--"],
+        block_comment: Some(("/*", "*/")),
+        quotes: "'\""
+    },
+    Language {
+        names: &["sh", "bash", "shell", "zsh"],
+        keywords: &["if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case",
+                    "esac", "in", "function", "return", "export", "local"],
+        case_insensitive: false,
+        line_comment: &["#"],
+        block_comment: None,
+        quotes: "'\""
+    },
+    Language {
+        names: &["python", "py"],
+        keywords: &["and", "as", "assert", "break", "class", "continue", "def", "del", "elif", "else",
+                    "except", "False", "finally", "for", "from", "if", "import", "in", "is", "lambda",
+                    "None", "not", "or", "pass", "raise", "return", "True", "try", "while", "with", "yield"],
+        case_insensitive: false,
+        line_comment: &["#"],
+        block_comment: None,
+        quotes: "'\""
+    },
+    Language {
+        names: &["javascript", "js", "typescript", "ts", "json"],
+        keywords: &["break", "case", "catch", "class", "const", "continue", "default", "else", "export",
+                    "false", "for", "function", "if", "import", "in", "let", "new", "null", "return",
+                    "switch", "this", "throw", "true", "try", "typeof", "undefined", "var", "while"],
+        case_insensitive: false,
+        line_comment: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: "'\"`"
+    }
+];
+
+fn language(syntax: &str) -> Option<&'static Language> {
+    let syntax = syntax.to_lowercase();
+    LANGUAGES.iter().find(|language| language.names.contains(&syntax.as_str()))
+}
+
+/// Splits the code into tokens to highlight, by the syntax of its block. Code in
+/// unknown syntaxes is a single plain token.
+pub fn tokens<'a>(syntax: Option<&str>, code: &'a str) -> Vec<(Token, &'a str)> {
+    let language = match syntax.and_then(language) {
+        Some(language) => language,
+        None => return vec![(Token::Plain, code)]
+    };
+    let mut tokens: Vec<(Token, &'a str)> = vec![];
+    let mut start = 0;
+    while start < code.len() {
+        let rest = &code[start..];
+        let (token, len) = next_token(language, rest);
+        match tokens.last_mut() {
+            // runs of plain text are one token
+            Some(&mut (Token::Plain, ref mut text)) if token == Token::Plain => {
+                *text = &code[start - text.len()..start + len];
+            },
+            _ => tokens.push((token, &rest[..len]))
+        }
+        start += len;
+    }
+    tokens
+}
+
+/// The first token of the text, and its length in bytes
+fn next_token(language: &Language, text: &str) -> (Token, usize) {
+    if language.line_comment.iter().any(|start| text.starts_with(start)) {
+        return (Token::Comment, text.find('\n').unwrap_or(text.len()));
+    }
+    if let Some((start, end)) = language.block_comment {
+        if text.starts_with(start) {
+            let len = text[start.len()..].find(end)
+                .map_or(text.len(), |idx| start.len() + idx + end.len());
+            return (Token::Comment, len);
+        }
+    }
+    let first = text.chars().next().expect("tokens are not empty");
+    if language.quotes.contains(first) {
+        let mut escaped = false;
+        for (idx, c) in text.char_indices().skip(1) {
+            if c == first && !escaped {
+                return (Token::Str, idx + c.len_utf8());
+            }
+            if c == '\n' && first != '`' {
+                // unterminated
+                return (Token::Str, idx);
+            }
+            escaped = c == '\\' && !escaped;
+        }
+        return (Token::Str, text.len());
+    }
+    if first.is_alphanumeric() || first == '_' {
+        let len = text.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(text.len());
+        let word = &text[..len];
+        if first.is_ascii_digit() {
+            return (Token::Number, len);
+        }
+        // dots only belong to numbers
+        let len = word.find('.').unwrap_or(len);
+        let word = &word[..len];
+        let keyword = if language.case_insensitive {
+            language.keywords.iter().any(|keyword| keyword.eq_ignore_ascii_case(word))
+        } else {
+            language.keywords.contains(&word)
+        };
+        return (if keyword { Token::Keyword } else { Token::Plain }, len);
+    }
+    (Token::Plain, first.len_utf8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::Token::*;
+
+    #[test]
+    fn rust() {
+        assert_eq!(vec![(Keyword, "fn"), (Plain, " main() { "), (Keyword, "let"), (Plain, " s = "),
+                        (Str, "\"a \\\" b\""), (Plain, "; "), (Comment, "// 1.5"), (Plain, "\n x."),
+                        (Number, "2"), (Plain, " "), (Comment, "/* c */"), (Plain, " }")],
+                   tokens(Some("rust"), "fn main() { let s = \"a \\\" b\"; // 1.5\n x.2 /* c */ }"));
+    }
+
+    #[test]
+    fn sql() {
+        assert_eq!(vec![(Keyword, "SELECT"), (Plain, " name "), (Keyword, "from"), (Plain, " items "),
+                        (Keyword, "Where"), (Plain, " id = "), (Number, "4.5"), (Plain, " "), (Comment, "-- 'x'")],
+                   tokens(Some("SQL"), "SELECT name from items Where id = 4.5 -- 'x'"));
+    }
+
+    #[test]
+    fn unknown() {
+        assert_eq!(vec![(Plain, "fn x")], tokens(Some("cobol"), "fn x"));
+        assert_eq!(vec![(Plain, "fn x")], tokens(None, "fn x"));
+        assert_eq!(vec![(Plain, "")], tokens(None, ""));
+    }
+}
@@ -0,0 +1,121 @@
+/// Undo/redo history of snapshots of some state
+///
+/// A snapshot is recorded before each change. Consecutive changes in the same group
+/// (e.g. keystrokes editing the same item) are undone together.
+pub struct History<T, G> {
+    undo: Vec<T>,
+    redo: Vec<T>,
+    last_group: Option<G>,
+    limit: usize
+}
+
+impl<T: PartialEq, G: PartialEq> History<T, G> {
+    pub fn new(limit: usize) -> Self {
+        History {
+            undo: vec![],
+            redo: vec![],
+            last_group: None,
+            limit
+        }
+    }
+
+    /// Records the state before a change. Changes without a group are never merged.
+    pub fn record(&mut self, state: T, group: Option<G>) {
+        let same_group = group.is_some() && group == self.last_group;
+        self.last_group = group;
+        if same_group || self.undo.last() == Some(&state) {
+            return;
+        }
+        self.undo.push(state);
+        self.redo.clear();
+        if self.undo.len() > self.limit {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Takes the current state, and returns the one to go back to
+    pub fn undo(&mut self, current: T) -> Option<T> {
+        self.last_group = None;
+        let previous = self.undo.pop()?;
+        self.redo.push(current);
+        Some(previous)
+    }
+
+    /// Takes the current state, and returns the one to go forward to
+    pub fn redo(&mut self, current: T) -> Option<T> {
+        self.last_group = None;
+        let next = self.redo.pop()?;
+        self.undo.push(current);
+        Some(next)
+    }
+
+    /// Forgets all changes, e.g. when the state is replaced by something unrelated
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.last_group = None;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_redo() {
+        let mut history: History<u32, ()> = History::new(10);
+        history.record(1, None);
+        history.record(2, None);
+        assert_eq!(Some(2), history.undo(3));
+        assert_eq!(Some(1), history.undo(2));
+        assert_eq!(None, history.undo(1));
+        assert_eq!(Some(2), history.redo(1));
+        assert_eq!(Some(3), history.redo(2));
+        assert!(!history.can_redo());
+
+        history.record(3, None);
+        assert_eq!(Some(3), history.undo(4));
+        history.record(3, None);
+        assert!(!history.can_redo(), "a new change discards the redo history");
+    }
+
+    #[test]
+    fn groups() {
+        let mut history = History::new(10);
+        history.record("a", Some(1));
+        history.record("ab", Some(1));
+        history.record("abc", Some(2));
+        history.record("abcd", None);
+        history.record("abcde", None);
+        assert_eq!(Some("abcde"), history.undo("abcdef"));
+        assert_eq!(Some("abcd"), history.undo("abcde"));
+        assert_eq!(Some("abc"), history.undo("abcd"));
+        assert_eq!(Some("a"), history.undo("abc"));
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn limit() {
+        let mut history: History<u32, ()> = History::new(2);
+        for state in 0..5 {
+            history.record(state, None);
+        }
+        assert_eq!(Some(4), history.undo(5));
+        assert_eq!(Some(3), history.undo(4));
+        assert_eq!(None, history.undo(3));
+
+        history.record(7, Some(()));
+        history.clear();
+        history.record(8, Some(()));
+        assert_eq!(Some(8), history.undo(9), "no group to join after clearing");
+        assert!(!history.can_undo());
+    }
+}
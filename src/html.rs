@@ -0,0 +1,129 @@
+use itemtree::{ItemTree, ItemId};
+use itemtree::ItemKind::*;
+use highlight;
+
+/// The stylesheet of the web UI, which also styles exported pages
+const APP_CSS: &'static str = include_str!("../static/app.css");
+
+/// How an exported page gets its styles
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stylesheet {
+    /// Included in the page, so it can be opened on its own
+    Inline,
+    /// Linked, e.g. "app.css" when published next to the web UI
+    Link(String)
+}
+
+/// The tree as a standalone, read-only HTML page
+pub fn to_html(tree: &ItemTree) -> String {
+    to_html_with(tree, &Stylesheet::Inline)
+}
+
+/// The tree as a read-only HTML page: items as nested lists with a class per kind
+/// (e.g. `kind-planned`), verbatim blocks as highlighted `<pre>` blocks, and items with children
+/// as `<details>`, which are collapsed by clicking them
+pub fn to_html_with(tree: &ItemTree, stylesheet: &Stylesheet) -> String {
+    let title = escape(&tree.title());
+    let style = match *stylesheet {
+        Stylesheet::Inline => format!("<style>\n{}</style>", APP_CSS),
+        Stylesheet::Link(ref href) => format!("<link rel=\"stylesheet\" href=\"{}\">", escape(href))
+    };
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n");
+    out.push_str("<meta charset=\"utf-8\">\n");
+    out.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\n");
+    out.push_str(&format!("<title>{}</title>\n{}\n", title, style));
+    out.push_str("</head>\n<body class=\"export\">\n");
+    out.push_str(&format!("<h1 class=\"document-title\">{}</h1>\n", title));
+    write_list(tree, tree.root(), &mut out);
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn write_list(tree: &ItemTree, parent: ItemId, out: &mut String) {
+    let children = &tree.nodes[parent].children_ids;
+    if children.is_empty() {
+        return;
+    }
+    out.push_str("<ul class=\"nodes\">\n");
+    for &id in children {
+        let item = &tree.nodes[id];
+        if let Verbatim(ref syntax) = item.kind {
+            let attribute = syntax.as_ref()
+                .map_or("".to_string(), |syntax| format!(" data-syntax=\"{}\"", escape(syntax)));
+            out.push_str(&format!("<li><pre class=\"node-value-verbatim kind-verbatim\"{}>", attribute));
+            for (token, text) in highlight::tokens(syntax.as_ref().map(|syntax| syntax.as_str()), &item.text) {
+                match token.class() {
+                    Some(class) => out.push_str(&format!("<span class=\"{}\">{}</span>", class, escape(text))),
+                    None => out.push_str(&escape(text))
+                }
+            }
+            out.push_str("</pre>\n");
+            write_list(tree, id, out);
+            out.push_str("</li>\n");
+            continue;
+        }
+        let class = format!("node-value kind-{}", item.kind.name());
+        if item.children_ids.is_empty() {
+            out.push_str(&format!("<li><span class=\"{}\">{}</span></li>\n", class, escape(&item.display())));
+        } else {
+            out.push_str(&format!("<li><details open><summary class=\"{}\">{}</summary>\n", class, escape(&item.display())));
+            write_list(tree, id, out);
+            out.push_str("</details></li>\n");
+        }
+    }
+    out.push_str("</ul>\n");
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itemtree::Item;
+
+    #[test]
+    fn export() {
+        let mut tree = ItemTree::parse("Plans & more", "- home\n  ? paint <walls>\n# clean\n");
+        let root = tree.root();
+        tree.add_child(root, Item::leaf(Verbatim(Some("sh".to_string())), "make deploy\n  --prod # \"live\""));
+        let html = to_html_with(&tree, &Stylesheet::Link("app.css".to_string()));
+        assert!(html.contains("<title>Plans &amp; more</title>\n<link rel=\"stylesheet\" href=\"app.css\">\n"));
+        let body = &html[html.find("<body").unwrap()..];
+        assert_eq!("<body class=\"export\">\n\
+                    <h1 class=\"document-title\">Plans &amp; more</h1>\n\
+                    <ul class=\"nodes\">\n\
+                    <li><details open><summary class=\"node-value kind-info\">- home</summary>\n\
+                    <ul class=\"nodes\">\n\
+                    <li><span class=\"node-value kind-planned\">? paint &lt;walls&gt;</span></li>\n\
+                    </ul>\n\
+                    </details></li>\n\
+                    <li><span class=\"node-value kind-done\"># clean</span></li>\n\
+                    <li><pre class=\"node-value-verbatim kind-verbatim\" data-syntax=\"sh\">make deploy\n  --prod \
+                    <span class=\"syntax-comment\"># &quot;live&quot;</span></pre>\n\
+                    </li>\n\
+                    </ul>\n\
+                    </body>\n</html>\n",
+                   body);
+    }
+
+    #[test]
+    fn inline_stylesheet() {
+        let html = to_html(&ItemTree::new("Plans"));
+        assert!(html.contains(".kind-done {"));
+        assert!(!html.contains("<link"));
+        assert!(!html.contains("<ul"), "no list without items");
+    }
+}
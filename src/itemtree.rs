@@ -1,12 +1,30 @@
 use self::ItemKind::*;
-use std::collections::HashMap;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 // assume copy
 pub type ItemId = usize;
 
+/// Identifies a node regardless of its `ItemId`, which changes when the tree is compacted.
+/// Keys are unique within the process, and are kept when nodes are moved, removed
+/// (to be pasted back), or appended to another tree.
+pub type NodeKey = usize;
+
+static NEXT_KEY: AtomicUsize = AtomicUsize::new(1);
+
+fn new_key() -> NodeKey {
+    NEXT_KEY.fetch_add(1, Ordering::Relaxed)
+}
+
 const INDENT_SZ: usize = 2;
 
-#[derive(Clone, Debug, PartialEq)]
+/// In JSON, e.g. `{"kind": "verbatim", "syntax": "rust"}`, see `json` (also for deserializing)
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "syntax", rename_all = "kebab-case")]
 pub enum ItemKind {
     Planned,
     Doing,
@@ -33,6 +51,20 @@ impl ItemKind {
         }
     }
 
+    /// The name used in exported documents, as in JSON
+    pub fn name(&self) -> &'static str {
+        match self {
+            Planned => "planned",
+            Doing => "doing",
+            Done => "done",
+            Blocked => "blocked",
+            Info => "info",
+            Blank => "blank",
+            BlankLine => "blank-line",
+            Verbatim(_syntax) => "verbatim"
+        }
+    }
+
     pub fn parse(text: &str) -> (ItemKind, &str) {
         let first = text.chars().next();
         match first {
@@ -41,7 +73,10 @@ impl ItemKind {
             Some('#') => (Done, &text[1..]),
             Some('!') => (Blocked, &text[1..]),
             Some('-') => (Info, &text[1..]),
-            Some('|') => (Verbatim(None), &text[1..]),
+            Some('|') => match lang_tag(&text[1..]) {
+                Some(syntax) => (Verbatim(Some(syntax)), &text[text.len()..]),
+                None => (Verbatim(None), &text[1..])
+            },
             // default to info
             None => (Info, text),
             _ => (Info, text)
@@ -49,11 +84,79 @@ impl ItemKind {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// The syntax of a verbatim block, given by a line like "|lang=sql" before its first line
+fn lang_tag(annotation: &str) -> Option<String> {
+    if annotation.starts_with("lang=") {
+        syntax_tag(&annotation["lang=".len()..])
+    } else {
+        None
+    }
+}
+
+/// A syntax name, e.g. "rust" of a line like "|rust". Such a line only gives the syntax
+/// of a block when followed by lines of the block (see `ItemTree::parse`), otherwise it
+/// is text, as in documents from before there were syntaxes.
+fn syntax_tag(tag: &str) -> Option<String> {
+    let valid = tag.chars().next().map_or(false, char::is_alphanumeric) &&
+        tag.chars().all(|c| c.is_alphanumeric() || "+-#._".contains(c));
+    if valid { Some(tag.to_string()) } else { None }
+}
+
+/// In JSON, a node of the flat form, see `json`
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Item {
+    #[serde(flatten)]
+    pub kind: ItemKind,
+    pub text: String,
+    #[serde(rename = "children", default)]
+    pub children_ids: Vec<ItemId>,
+    #[serde(skip)]
+    pub source: Option<SourceLine>
+}
+
+/// Formatting details of the line an item was parsed from, enough to write it back
+/// exactly as it was, as long as the item is not edited
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceLine {
+    /// Leading whitespace, as written (tabs and/or spaces)
+    pub indent: String,
+    /// Whether the line had a bullet, lines without one are parsed as `Info`
+    pub bullet: bool,
+    /// Whitespace between the bullet and the text
+    pub spacing: String,
+    /// Whitespace after the text
+    pub trailing: String,
+    /// "\n", "\r\n", or "" for a last line without line ending
+    pub line_ending: String,
+    /// The depth in the tree the item was placed at when parsed
+    pub depth: usize,
+    /// The kind and text as parsed, an item differing from these has been edited
     pub kind: ItemKind,
     pub text: String,
-    pub children_ids: Vec<ItemId>
+    /// For verbatim blocks, all their lines as written, with line endings
+    pub lines: Vec<String>
+}
+
+impl SourceLine {
+    /// The column of the first non whitespace character, tabs count as one indentation step
+    pub fn column(&self) -> usize {
+        indent_width(&self.indent)
+    }
+}
+
+fn indent_width(indent: &str) -> usize {
+    indent.chars()
+        .map(|c| if c == '\t' { INDENT_SZ } else { 1 })
+        .sum()
+}
+
+/// Source lines are formatting details, and do not take part in equality
+impl PartialEq for Item {
+    fn eq(&self, other: &Item) -> bool {
+        self.kind == other.kind &&
+            self.text == other.text &&
+            self.children_ids == other.children_ids
+    }
 }
 
 
@@ -63,7 +166,8 @@ impl Item {
         Item {
             kind,
             text: text.to_string(),
-            children_ids: vec![]
+            children_ids: vec![],
+            source: None
         }
     }
 
@@ -89,26 +193,62 @@ impl Item {
     }
 }
 
-// TODO: Eq not really true since nodes
-// could have different ids but be the same tree
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct ItemTree {
     pub nodes: Vec<Item>,
-    pub parents: Vec<Option<ItemId>>
+    pub parents: Vec<Option<ItemId>>,
+    pub keys: Vec<NodeKey>
+}
+
+/// Trees are equal if they have the same structure, kinds and texts,
+/// regardless of ids, keys and removed nodes
+impl PartialEq for ItemTree {
+    fn eq(&self, other: &ItemTree) -> bool {
+        self.same_subtree(self.root(), other, other.root())
+    }
 }
 
 impl ItemTree {
+    /// Parses a line (without line ending), keeping its formatting details in `Item::source`
     pub fn parse_line(line: &str) -> (usize, Item) {
-        let nonspace_idx = line.find(|c| !char::is_whitespace(c));
-        let spaces = nonspace_idx.unwrap_or(0);
-        let indent = spaces / INDENT_SZ;
+        let content_start = line.find(|c| !char::is_whitespace(c))
+            .unwrap_or(line.len());
+        let (indent_str, rest) = line.split_at(content_start);
+        let (content, trailing) = rest.split_at(rest.trim_end().len());
 
-        let mut node = Item::parse(&line[spaces..]);
-        if indent == 0 && node.kind == Blank {
+        let mut node = Item::parse(content);
+        if node.kind == Blank {
+            // blank lines are blank regardless of their whitespace
             node.kind = BlankLine;
         }
+        let trailing = if node.kind == Verbatim(None) {
+            // verbatim lines keep their trailing whitespace
+            node.text = Item::parse(rest).text;
+            ""
+        } else {
+            trailing
+        };
+        let (_, after_bullet) = ItemKind::parse(content);
+        let bullet = after_bullet.len() < content.len();
+        let spacing = if bullet && after_bullet.starts_with(' ') { " " } else { "" };
+
+        let indent = if node.kind == BlankLine {
+            0
+        } else {
+            indent_width(indent_str) / INDENT_SZ
+        };
+        node.source = Some(SourceLine {
+            indent: indent_str.to_string(),
+            bullet,
+            spacing: spacing.to_string(),
+            trailing: trailing.to_string(),
+            line_ending: "".to_string(),
+            depth: indent,
+            kind: node.kind.clone(),
+            text: node.text.clone(),
+            lines: vec![]
+        });
 
-        //println!("indent: {:?}, node: {:?}", indent, node);
         (indent, node)
     }
 
@@ -117,26 +257,102 @@ impl ItemTree {
         let mut tree = ItemTree::new(title);
 
         let mut last_at_indent = HashMap::new();
+        let mut depths = vec![0];
         let root = tree.root();
+        let mut last_item = root;
+        // whether the last item is a verbatim block with lines, and not just a syntax line
+        let mut block_has_lines = false;
+        // whether the last item is a verbatim block of a single line like "|rust", which
+        // gives the syntax if lines like "| fn main() {" follow
+        let mut maybe_syntax = false;
+
+        // blank lines wait for the next item, and are placed at its level
+        let mut blank_lines = vec![];
 
-        for line in content.lines() {
-            let (indent, child) = ItemTree::parse_line(line);
-            let is_blankline = &child.kind == &BlankLine;
-            let parent_id = *last_at_indent.get(&indent).unwrap_or(&root);
-            let id = tree.add_child(parent_id, child);
-            if !is_blankline {
-                // blanklines can't have children
-                last_at_indent.insert(indent + 1, id);
+        for raw_line in split_lines(content) {
+            let line = raw_line.trim_end_matches(|c| c == '\n' || c == '\r');
+            let (indent, mut child) = ItemTree::parse_line(line);
+            if let Some(ref mut source) = child.source {
+                source.line_ending = raw_line[line.len()..].to_string();
+                if let Verbatim(_) = child.kind {
+                    source.lines.push(raw_line.to_string());
+                }
             }
+            if child.kind == BlankLine {
+                blank_lines.push(child);
+                continue;
+            }
+            // over-indented lines go below the closest less indented item
+            let parent_id = (0..indent + 1).rev()
+                .filter_map(|i| last_at_indent.get(&i))
+                .next().cloned()
+                .unwrap_or(root);
+            if blank_lines.is_empty() && child.kind == Verbatim(None) && tree.parent(last_item) == Some(parent_id) {
+                if let Verbatim(_) = tree.nodes[last_item].kind {
+                    // consecutive verbatim lines at the same level form a block
+                    let block = &mut tree.nodes[last_item];
+                    let is_content_line = child.text.is_empty() || line.trim_start().starts_with("| ");
+                    if maybe_syntax && is_content_line {
+                        block.kind = Verbatim(Some(mem::replace(&mut block.text, String::new())));
+                        block_has_lines = false;
+                    }
+                    maybe_syntax = false;
+                    if block_has_lines {
+                        block.text.push('\n');
+                    }
+                    block.text.push_str(&child.text);
+                    if let Some(ref mut source) = block.source {
+                        source.kind = block.kind.clone();
+                        source.text = block.text.clone();
+                        source.lines.push(raw_line.to_string());
+                    }
+                    block_has_lines = true;
+                    continue;
+                }
+            }
+            tree.add_blank_lines(parent_id, &mut blank_lines, &mut depths);
+            block_has_lines = child.kind == Verbatim(None);
+            maybe_syntax = child.kind == Verbatim(None) && syntax_tag(&child.text).is_some() &&
+                child.source.as_ref().map_or(false, |source| source.spacing.is_empty());
+            let id = tree.add_parsed(parent_id, child, &mut depths);
+            last_item = id;
+            last_at_indent.insert(indent + 1, id);
+            // deeper items are no longer open, lines can only be added in document order
+            last_at_indent.retain(|i, _| *i <= indent + 1);
         }
+        tree.add_blank_lines(root, &mut blank_lines, &mut depths);
 
         tree
     }
 
+    fn add_blank_lines(&mut self, parent: ItemId, blank_lines: &mut Vec<Item>, depths: &mut Vec<usize>) {
+        for blank_line in blank_lines.drain(..) {
+            self.add_parsed(parent, blank_line, depths);
+        }
+    }
+
+    /// Adds a parsed item, noting the depth it was placed at
+    fn add_parsed(&mut self, parent: ItemId, mut child: Item, depths: &mut Vec<usize>) -> ItemId {
+        let depth = depths[parent] + 1;
+        if let Some(ref mut source) = child.source {
+            source.depth = depth;
+        }
+        depths.push(depth);
+        self.add_child(parent, child)
+    }
+
+    /// A tree of the nodes, with new keys. The nodes and parents must describe a tree
+    /// rooted at the first node, as checked by `json::from_flat_json`.
+    pub fn from_arena(nodes: Vec<Item>, parents: Vec<Option<ItemId>>) -> Self {
+        let keys = nodes.iter().map(|_| new_key()).collect();
+        ItemTree { nodes, parents, keys }
+    }
+
     pub fn new(title: &str) -> Self {
         ItemTree {
             nodes: vec![Item::leaf(Info, title)],
-            parents: vec![None]
+            parents: vec![None],
+            keys: vec![new_key()]
         }
     }
 
@@ -147,44 +363,219 @@ impl ItemTree {
     }
 
     pub fn add_child(&mut self, parent: ItemId, child: Item) -> ItemId {
-        let id = self.nodes.len();
-        self.nodes.push(child);
-        self.parents.push(Some(parent));
+        let id = self.push_node(parent, child, new_key());
         self.nodes[parent].children_ids.push(id);
         id
     }
 
     pub fn add_child_at(&mut self, parent: ItemId, pos: usize, child: Item) -> ItemId {
-        let new_id = self.nodes.len();
-        self.nodes.push(child);
-        self.parents.push(Some(parent));
+        let new_id = self.push_node(parent, child, new_key());
         if pos >= self.nodes[parent].children_ids.len() {
             self.nodes[parent].children_ids.push(new_id)
         } else {
-            self.nodes[parent].children_ids.insert(new_id, pos);
+            self.nodes[parent].children_ids.insert(pos, new_id);
         }
         new_id
     }
 
+    /// Adds a node to the arena, without adding it to the children of `parent`
+    fn push_node(&mut self, parent: ItemId, child: Item, key: NodeKey) -> ItemId {
+        let id = self.nodes.len();
+        self.nodes.push(child);
+        self.parents.push(Some(parent));
+        self.keys.push(key);
+        id
+    }
+
+    pub fn key(&self, id: ItemId) -> NodeKey {
+        self.keys[id]
+    }
+
+    /// The node with the key, unless it has been removed
+    pub fn find(&self, key: NodeKey) -> Option<ItemId> {
+        (0..self.keys.len())
+            .find(|id| self.keys[*id] == key && !self.is_removed(*id))
+    }
+
+    /// Whether `ancestor` is `id` or one of its ancestors
+    pub fn is_ancestor(&self, ancestor: ItemId, id: ItemId) -> bool {
+        let mut curr = Some(id);
+        while let Some(node) = curr {
+            if node == ancestor {
+                return true;
+            }
+            curr = self.parent(node);
+        }
+        false
+    }
+
+    fn position_in_parent(&self, id: ItemId) -> Option<(ItemId, usize)> {
+        self.parent(id).map(|parent| {
+            let pos = self.nodes[parent].children_ids.iter()
+                .position(|child| *child == id).unwrap();
+            (parent, pos)
+        })
+    }
+
+    /// Moves a node, along with its offspring, to be child number `pos` of `new_parent`
+    /// (or the last child, if `pos` is out of range).
+    /// Nothing is moved, and false returned, if the node is the root or an ancestor of
+    /// `new_parent`, or if `new_parent` is a blank line.
+    pub fn move_node(&mut self, id: ItemId, new_parent: ItemId, pos: usize) -> bool {
+        if self.is_ancestor(id, new_parent) || self.nodes[new_parent].kind == BlankLine {
+            return false;
+        }
+        let (old_parent, old_pos) = match self.position_in_parent(id) {
+            Some(position) => position,
+            None => return false
+        };
+        self.nodes[old_parent].children_ids.remove(old_pos);
+        let pos = pos.min(self.nodes[new_parent].children_ids.len());
+        self.nodes[new_parent].children_ids.insert(pos, id);
+        self.parents[id] = Some(new_parent);
+        self.place_blank_lines(old_parent);
+        self.place_blank_lines(new_parent);
+        true
+    }
+
+    /// Blank lines after the last child of an item are parsed as the next siblings of
+    /// the item (see `parse`), so that is where they are moved after an edit
+    fn place_blank_lines(&mut self, parent: ItemId) {
+        let mut parent = parent;
+        while let Some((grandparent, pos)) = self.position_in_parent(parent) {
+            let (first_blank, child_count) = {
+                let children = &self.nodes[parent].children_ids;
+                let first_blank = children.iter()
+                    .rposition(|&id| self.nodes[id].kind != BlankLine)
+                    .map_or(0, |last_item| last_item + 1);
+                (first_blank, children.len())
+            };
+            if first_blank == child_count {
+                return;
+            }
+            let blank_lines = self.nodes[parent].children_ids.split_off(first_blank);
+            for &id in &blank_lines {
+                self.parents[id] = Some(grandparent);
+            }
+            let siblings = &mut self.nodes[grandparent].children_ids;
+            let after = siblings.split_off(pos + 1);
+            siblings.extend(blank_lines);
+            siblings.extend(after);
+            parent = grandparent;
+        }
+    }
+
+    /// Makes the node the last child of its previous sibling
+    pub fn indent(&mut self, id: ItemId) -> bool {
+        match self.position_in_parent(id) {
+            Some((parent, pos)) if pos > 0 => {
+                let new_parent = self.nodes[parent].children_ids[pos - 1];
+                let new_pos = self.nodes[new_parent].children_ids.len();
+                self.move_node(id, new_parent, new_pos)
+            },
+            _ => false
+        }
+    }
+
+    /// Makes the node the next sibling of its parent
+    pub fn outdent(&mut self, id: ItemId) -> bool {
+        let parent_position = self.parent(id)
+            .and_then(|parent| self.position_in_parent(parent));
+        match parent_position {
+            Some((grandparent, parent_pos)) => self.move_node(id, grandparent, parent_pos + 1),
+            None => false
+        }
+    }
+
+    /// Swaps place with the previous sibling
+    pub fn move_up(&mut self, id: ItemId) -> bool {
+        match self.position_in_parent(id) {
+            Some((parent, pos)) if pos > 0 => {
+                self.nodes[parent].children_ids.swap(pos - 1, pos);
+                self.place_blank_lines(parent);
+                true
+            },
+            _ => false
+        }
+    }
+
+    /// Swaps place with the next sibling
+    pub fn move_down(&mut self, id: ItemId) -> bool {
+        match self.position_in_parent(id) {
+            Some((parent, pos)) if pos + 1 < self.nodes[parent].children_ids.len() => {
+                self.nodes[parent].children_ids.swap(pos, pos + 1);
+                self.place_blank_lines(parent);
+                true
+            },
+            _ => false
+        }
+    }
+
     pub fn remove_if_leaf(&mut self, item_id: ItemId) -> bool {
-        let parent_id = self.parent(item_id);
         let num_children = self.nodes[item_id].children_ids.len();
-        parent_id.map(|parent| {
-            if num_children == 0 {
-                let index = self.nodes[parent].children_ids.iter()
-                    .position(|child| *child == item_id).unwrap();
-                self.nodes[parent].children_ids
-                    .remove(index);
-                true
-            } else {
-                false
-            }
-        }).unwrap_or(false)
+        num_children == 0 && self.remove_subtree(item_id).is_some()
+    }
+
+    /// Removes a node and its offspring, returning them as a tree of their own
+    /// (with the removed node as root, keeping its kind), or `None` for the root.
+    ///
+    /// Ids of the remaining nodes are not changed, the slots of the removed nodes are
+    /// left as tombstones (see `is_removed`).
+    pub fn remove_subtree(&mut self, id: ItemId) -> Option<ItemTree> {
+        let (parent, pos) = self.position_in_parent(id)?;
+        self.nodes[parent].children_ids.remove(pos);
+        self.place_blank_lines(parent);
+
+        let mut item = self.take_node(id);
+        let children = mem::replace(&mut item.children_ids, vec![]);
+        let mut removed = ItemTree {
+            nodes: vec![item],
+            parents: vec![None],
+            keys: vec![self.keys[id]]
+        };
+        for child_id in children {
+            self.move_subtree_into(child_id, &mut removed, 0);
+        }
+        Some(removed)
     }
 
+    fn move_subtree_into(&mut self, id: ItemId, other: &mut ItemTree, parent: ItemId) {
+        let mut item = self.take_node(id);
+        let children = mem::replace(&mut item.children_ids, vec![]);
+        let new_id = other.push_node(parent, item, self.keys[id]);
+        other.nodes[parent].children_ids.push(new_id);
+        for child_id in children {
+            self.move_subtree_into(child_id, other, new_id);
+        }
+    }
+
+    /// Leaves a tombstone in the place of the node
+    fn take_node(&mut self, id: ItemId) -> Item {
+        self.parents[id] = None;
+        mem::replace(&mut self.nodes[id], Item::leaf(Blank, ""))
+    }
+
+    /// Whether the node has been removed from the tree
+    pub fn is_removed(&self, id: ItemId) -> bool {
+        id != self.root() && self.parents[id].is_none()
+    }
+
+    /// Moves the nodes of `other` into this tree, with the root of `other` as the last child of `parent`.
+    /// Keys of `other` are kept, unless already used in this tree.
     pub fn append(&mut self, parent: ItemId, kind: ItemKind, other: &mut ItemTree) {
         let first_new_id = self.nodes.len();
 
+        let mut used_keys: HashSet<NodeKey> = (0..self.nodes.len())
+            .filter(|id| !self.is_removed(*id))
+            .map(|id| self.keys[id])
+            .collect();
+        for key in other.keys.iter_mut() {
+            if !used_keys.insert(*key) {
+                *key = new_key();
+            }
+        }
+        self.keys.append(&mut other.keys);
+
         other.bump_indices(0, first_new_id);
 
         self.nodes.append(&mut other.nodes);
@@ -215,6 +606,325 @@ impl ItemTree {
     pub fn parent(&self, id: ItemId) -> Option<ItemId> {
         self.parents[id]
     }
+
+    fn same_subtree(&self, node: ItemId, other: &ItemTree, other_node: ItemId) -> bool {
+        let item = &self.nodes[node];
+        let other_item = &other.nodes[other_node];
+        item.kind == other_item.kind &&
+            item.text == other_item.text &&
+            item.children_ids.len() == other_item.children_ids.len() &&
+            item.children_ids.iter().zip(other_item.children_ids.iter())
+                .all(|(child, other_child)| self.same_subtree(*child, other, *other_child))
+    }
+
+    /// Number of removed nodes still taking up space, see `compact`
+    pub fn removed_count(&self) -> usize {
+        (0..self.nodes.len()).filter(|id| self.is_removed(*id)).count()
+    }
+
+    /// Drops removed nodes from the arena, renumbering the remaining ones in document order.
+    /// Keys are kept. Returns the new id of every remaining node, by old id.
+    pub fn compact(&mut self) -> HashMap<ItemId, ItemId> {
+        let mut order = vec![];
+        self.collect_preorder(self.root(), &mut order);
+        let remap: HashMap<ItemId, ItemId> = order.iter().enumerate()
+            .map(|(new_id, old_id)| (*old_id, new_id))
+            .collect();
+
+        let mut nodes = Vec::with_capacity(order.len());
+        let mut parents = Vec::with_capacity(order.len());
+        let mut keys = Vec::with_capacity(order.len());
+        for old_id in &order {
+            let mut item = mem::replace(&mut self.nodes[*old_id], Item::leaf(Blank, ""));
+            item.children_ids = item.children_ids.iter().map(|child| remap[child]).collect();
+            nodes.push(item);
+            parents.push(self.parents[*old_id].map(|parent| remap[&parent]));
+            keys.push(self.keys[*old_id]);
+        }
+        self.nodes = nodes;
+        self.parents = parents;
+        self.keys = keys;
+        remap
+    }
+
+    fn collect_preorder(&self, node: ItemId, order: &mut Vec<ItemId>) {
+        order.push(node);
+        for child_id in &self.nodes[node].children_ids {
+            self.collect_preorder(*child_id, order);
+        }
+    }
+
+    /// Gives nodes the keys of the corresponding nodes in `previous` (e.g. an earlier parse
+    /// of the same document), so they keep their identity. Children are matched, in order,
+    /// by kind and text, or by text only (for changed kinds).
+    pub fn adopt_keys(&mut self, previous: &ItemTree) {
+        let mut used = HashSet::new();
+        let (root, previous_root) = (self.root(), previous.root());
+        self.keys[root] = previous.keys[previous_root];
+        self.adopt_keys_rec(root, previous, previous_root, &mut used);
+    }
+
+    fn adopt_keys_rec(&mut self, node: ItemId, previous: &ItemTree, previous_node: ItemId,
+                      used: &mut HashSet<ItemId>) {
+        for child_id in self.nodes[node].children_ids.clone() {
+            let matching = {
+                let child = &self.nodes[child_id];
+                let candidates = &previous.nodes[previous_node].children_ids;
+                let same_text = |id: &&ItemId| !used.contains(*id) &&
+                    previous.nodes[**id].text == child.text;
+                candidates.iter()
+                    .filter(&same_text)
+                    .find(|id| previous.nodes[**id].kind == child.kind)
+                    .or_else(|| candidates.iter().find(&same_text))
+                    .cloned()
+            };
+            if let Some(previous_child) = matching {
+                used.insert(previous_child);
+                self.keys[child_id] = previous.keys[previous_child];
+                self.adopt_keys_rec(child_id, previous, previous_child, used);
+            }
+        }
+    }
+
+    /// Number of items below the root, not counting blank lines
+    pub fn item_count(&self) -> usize {
+        self.count_offspring(self.root())
+    }
+
+    fn count_offspring(&self, node: ItemId) -> usize {
+        self.nodes[node].children_ids.iter()
+            .map(|child_id| {
+                let kind = &self.nodes[*child_id].kind;
+                let own = if *kind == Blank || *kind == BlankLine { 0 } else { 1 };
+                own + self.count_offspring(*child_id)
+            })
+            .sum()
+    }
+
+    /// The ancestors of a node, outermost first, excluding the root (title) node
+    pub fn ancestors(&self, id: ItemId) -> Vec<ItemId> {
+        let mut ancestors = vec![];
+        let mut curr = self.parent(id);
+        while let Some(ancestor) = curr {
+            if ancestor != self.root() {
+                ancestors.push(ancestor);
+            }
+            curr = self.parent(ancestor);
+        }
+        ancestors.reverse();
+        ancestors
+    }
+
+    /// Finds the node reached by following child positions (0-indexed) from the root
+    pub fn node_at(&self, path: &[usize]) -> Option<ItemId> {
+        let mut curr = self.root();
+        for pos in path {
+            curr = *self.nodes[curr].children_ids.get(*pos)?;
+        }
+        Some(curr)
+    }
+
+    /// The child positions leading from the root to the node, inverse of `node_at`
+    pub fn path_of(&self, id: ItemId) -> Vec<usize> {
+        let mut path = vec![];
+        let mut curr = id;
+        while let Some((parent, pos)) = self.position_in_parent(curr) {
+            path.push(pos);
+            curr = parent;
+        }
+        path.reverse();
+        path
+    }
+}
+
+/// How to serialize an `ItemTree` to text
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextMode {
+    /// Write a well formatted document, this is guaranteed to parse back into an equal tree,
+    /// except that a verbatim block without syntax is joined with a verbatim block before it
+    Normalized,
+    /// Write untouched items exactly as they were parsed (indentation, missing bullets,
+    /// trailing whitespace, line endings), and only normalize new or edited items
+    PreserveOriginal
+}
+
+impl ItemTree {
+    /// The well formatted textual representation of the tree
+    pub fn to_text(&self) -> String {
+        self.to_text_with(TextMode::Normalized)
+    }
+
+    pub fn to_text_with(&self, mode: TextMode) -> String {
+        let mut buffer = String::new();
+        self.write_text(&mut buffer, mode)
+            .expect("writing to a String can't fail");
+        buffer
+    }
+
+    pub fn write_text<W: fmt::Write>(&self, out: &mut W, mode: TextMode) -> fmt::Result {
+        let mut state = WriteState { open: BTreeMap::new(), missing_newline: false };
+        self.write_children(out, self.root(), 0, 0, mode, &mut state)
+    }
+
+    pub fn write_io<W: io::Write>(&self, out: &mut W, mode: TextMode) -> io::Result<()> {
+        out.write_all(self.to_text_with(mode).as_bytes())
+    }
+
+    fn write_children<W: fmt::Write>(&self, out: &mut W, parent: ItemId, depth: usize, parent_level: usize,
+                                     mode: TextMode, state: &mut WriteState) -> fmt::Result {
+        let children = &self.nodes[parent].children_ids;
+        let mut prev_level = None;
+        for (idx, &child_id) in children.iter().enumerate() {
+            let next_level = children[idx + 1..].iter()
+                .map(|&id| &self.nodes[id])
+                .find(|item| item.kind != BlankLine)
+                .and_then(|item| item.source.as_ref())
+                .map(|source| source.column() / INDENT_SZ);
+            let around = Around { depth: depth + 1, parent_level, prev_level, next_level };
+            let level = self.write_node(out, child_id, around, mode, state)?;
+            if self.nodes[child_id].kind != BlankLine {
+                prev_level = Some(level);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the item and its children, returning the indentation level it was written at.
+    /// When preserving, lines are kept at their source column as long as they are parsed
+    /// back below the same parent, and other lines are placed where they are.
+    fn write_node<W: fmt::Write>(&self, out: &mut W, node: ItemId, around: Around,
+                                 mode: TextMode, state: &mut WriteState) -> Result<usize, fmt::Error> {
+        let Around { depth, parent_level, prev_level, next_level } = around;
+        if state.missing_newline {
+            out.write_char('\n')?;
+            state.missing_newline = false;
+        }
+        let item = &self.nodes[node];
+        let source_level = item.source.as_ref().map(|source| source.column() / INDENT_SZ);
+        let level = match mode {
+            TextMode::Normalized => depth,
+            TextMode::PreserveOriginal => {
+                let parent = self.parent(node).unwrap_or(self.root());
+                let fallback = cmp::max(parent_level + 1, next_level.unwrap_or(0));
+                source_level.into_iter().chain(prev_level).chain(Some(fallback))
+                    .find(|&level| state.parent_at(level, self.root()) == parent)
+                    .unwrap_or(parent_level + 1)
+            }
+        };
+        let preserved = mode == TextMode::PreserveOriginal && item.is_unchanged_at(depth) &&
+            (item.kind == BlankLine || source_level == Some(level));
+        match item.source {
+            Some(ref source) if preserved && !source.lines.is_empty() => {
+                for line in &source.lines {
+                    out.write_str(line)?;
+                }
+                state.missing_newline = !source.lines.last()
+                    .map_or(false, |line| line.ends_with(|c| c == '\n' || c == '\r'));
+            },
+            Some(ref source) if preserved => {
+                out.write_str(&source.indent)?;
+                if source.bullet {
+                    out.write_str(item.kind.symbol())?;
+                }
+                out.write_str(&source.spacing)?;
+                out.write_str(&item.text)?;
+                out.write_str(&source.trailing)?;
+                out.write_str(&source.line_ending)?;
+                state.missing_newline = source.line_ending.is_empty();
+            },
+            _ => {
+                let indent = match item.source {
+                    // edited items keep their indentation
+                    Some(ref source) if mode == TextMode::PreserveOriginal && source_level == Some(level) =>
+                        source.indent.clone(),
+                    _ => " ".repeat(level * INDENT_SZ)
+                };
+                if let Verbatim(ref syntax) = item.kind {
+                    if let Some(ref syntax) = *syntax {
+                        // a line like "|rust" would continue a block right before
+                        let follows_block = self.position_in_parent(node)
+                            .and_then(|(parent, idx)| idx.checked_sub(1).map(|prev| self.nodes[parent].children_ids[prev]))
+                            .map_or(false, |prev| if let Verbatim(_) = self.nodes[prev].kind { true } else { false });
+                        let tag = if follows_block { "lang=" } else { "" };
+                        write!(out, "{}|{}{}\n", indent, tag, syntax)?;
+                    }
+                    for line in item.text.split('\n') {
+                        if line.is_empty() {
+                            write!(out, "{}|\n", indent)?;
+                        } else {
+                            write!(out, "{}| {}\n", indent, line)?;
+                        }
+                    }
+                } else {
+                    if item.kind != BlankLine && item.kind != Blank {
+                        out.write_str(&indent)?;
+                        out.write_str(&item.display())?;
+                    }
+                    out.write_char('\n')?;
+                }
+            }
+        }
+        if item.kind != BlankLine {
+            state.open(level, node);
+        }
+        self.write_children(out, node, depth, level, mode, state)?;
+        Ok(level)
+    }
+}
+
+/// The depth of an item being written, and the indentation levels of the lines around it
+#[derive(Clone, Copy)]
+struct Around {
+    depth: usize,
+    /// The level its parent was written at
+    parent_level: usize,
+    /// The level of the previous sibling, not counting blank lines
+    prev_level: Option<usize>,
+    /// The source level of the next sibling, not counting blank lines
+    next_level: Option<usize>
+}
+
+/// What parsing the text written so far would have seen, see `ItemTree::parse`
+struct WriteState {
+    /// The items that lines are placed below, by their indentation level + 1
+    open: BTreeMap<usize, ItemId>,
+    missing_newline: bool
+}
+
+impl WriteState {
+    /// The item a line at the indentation level is placed below
+    fn parent_at(&self, level: usize, root: ItemId) -> ItemId {
+        self.open.range(..level + 1).next_back().map_or(root, |(_, &id)| id)
+    }
+
+    /// Lines are only placed below the item written last, or items it is placed below
+    fn open(&mut self, level: usize, id: ItemId) {
+        self.open.insert(level + 1, id);
+        self.open.split_off(&(level + 2));
+    }
+}
+
+impl Item {
+    /// Whether the source line still describes this item, if placed at `depth`
+    fn is_unchanged_at(&self, depth: usize) -> bool {
+        self.source.as_ref().map(|source| {
+            source.depth == depth && source.kind == self.kind && source.text == self.text
+        }).unwrap_or(false)
+    }
+}
+
+/// Splits into lines, keeping line endings, unlike `str::lines`
+fn split_lines(content: &str) -> Vec<&str> {
+    let mut lines = vec![];
+    let mut start = 0;
+    for (idx, _) in content.match_indices('\n') {
+        lines.push(&content[start..idx + 1]);
+        start = idx + 1;
+    }
+    if start < content.len() {
+        lines.push(&content[start..]);
+    }
+    lines
 }
 
 pub fn child(text: &str, children: &mut [(ItemKind, ItemTree)]) -> ItemTree {
@@ -272,6 +982,395 @@ mod tests {
         );
     }
 
+    #[test]
+    fn paths() {
+        let doc = "- dude\n  * sweet\n  ? what";
+        let tree = ItemTree::parse("the doc", doc);
+        let what = tree.node_at(&[0, 1]).unwrap();
+        assert_eq!("what", tree.nodes[what].text);
+        assert_eq!(vec![0, 1], tree.path_of(what));
+        assert_eq!(vec![tree.node_at(&[0]).unwrap()], tree.ancestors(what));
+        assert_eq!(Some(tree.root()), tree.node_at(&[]));
+        assert_eq!(None, tree.node_at(&[0, 2]));
+    }
+
+    #[test]
+    fn well_formatted_round_trip() {
+        let doc = "  - dude\n    * sweet\n      | code\n    ? what\n\n  # done\n";
+        let tree = ItemTree::parse("the doc", doc);
+        assert_eq!(doc, tree.to_text());
+        assert_eq!(tree, ItemTree::parse("the doc", &tree.to_text()));
+    }
+
+    #[test]
+    fn normalize() {
+        let doc = "dude\n   *sweet  \r\n\tsome info";
+        let tree = ItemTree::parse("the doc", doc);
+        assert_eq!("  - dude\n    * sweet\n    - some info\n", tree.to_text());
+    }
+
+    #[test]
+    fn preserve_original() {
+        let doc = "dude\n   *sweet  \r\n\t\n? what\n\t\t|  code \n  \n- later";
+        let mut tree = ItemTree::parse("the doc", doc);
+        assert_eq!(doc, tree.to_text_with(TextMode::PreserveOriginal));
+
+        let sweet = tree.node_at(&[0, 0]).unwrap();
+        tree.nodes[sweet].kind = Done;
+        let new_id = tree.add_child(tree.root(), Item::leaf(Info, "new"));
+        assert_eq!("dude\n   # sweet\n\t\n? what\n\t\t|  code \n  \n- later\n- new\n",
+                   tree.to_text_with(TextMode::PreserveOriginal));
+
+        tree.remove_if_leaf(new_id);
+        let mut out = vec![];
+        tree.write_io(&mut out, TextMode::PreserveOriginal).unwrap();
+        assert_eq!("dude\n   # sweet\n\t\n? what\n\t\t|  code \n  \n- later",
+                   String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn preserve_edited_parents() {
+        let doc = "- a\n  - b\n- c";
+        let mut tree = ItemTree::parse("the doc", doc);
+        let a = tree.node_at(&[0]).unwrap();
+        tree.nodes[a].kind = Done;
+        assert_eq!("# a\n  - b\n- c", tree.to_text_with(TextMode::PreserveOriginal));
+
+        // items moved below an edited parent, and new items, are placed at the indentation
+        // of their siblings
+        let doc = "- z\n- a\n    - b\n- c\n";
+        let mut tree = ItemTree::parse("the doc", doc);
+        let (a, c) = (tree.node_at(&[1]).unwrap(), tree.node_at(&[2]).unwrap());
+        tree.nodes[a].text = "edited".to_string();
+        tree.indent(c);
+        tree.add_child(a, Item::leaf(Planned, "new"));
+        let root = tree.root();
+        tree.add_child_at(root, 0, Item::leaf(Info, "first"));
+        let text = tree.to_text_with(TextMode::PreserveOriginal);
+        assert_eq!("  - first\n- z\n- edited\n    - b\n    - c\n    ? new\n", text);
+        assert_eq!(tree, ItemTree::parse("the doc", &text));
+    }
+
+    #[test]
+    fn source_details() {
+        let (indent, item) = ItemTree::parse_line("\t  untitled \t");
+        assert_eq!(2, indent);
+        assert_eq!(Item::leaf(Info, "untitled"), item);
+        let source = item.source.unwrap();
+        assert_eq!("\t  ", source.indent);
+        assert_eq!(4, source.column());
+        assert!(!source.bullet);
+        assert_eq!(" \t", source.trailing);
+
+        let (indent, item) = ItemTree::parse_line("   ?slim");
+        assert_eq!(1, indent);
+        let source = item.source.unwrap();
+        assert!(source.bullet);
+        assert_eq!("", source.spacing);
+    }
+
+    #[test]
+    fn blank_lines_keep_position() {
+        let doc = "  - a\n    - b\n\n    - c\n";
+        let tree = ItemTree::parse("the doc", doc);
+        assert_eq!(doc, tree.to_text());
+        assert_eq!(3, tree.item_count());
+        let c = tree.node_at(&[0, 2]).unwrap();
+        assert_eq!("c", tree.nodes[c].text);
+
+        // blank lines are siblings of the next item, not children of the previous one
+        let doc = "- a\n  - b\n    - c\n\n  - d\n\n- e\n\n";
+        let tree = ItemTree::parse("the doc", doc);
+        assert_eq!(
+            child("the doc", &mut [
+                (Info, child("a", &mut [
+                    (Info, child("b", &mut [
+                        (Info, child("c", &mut []))
+                    ])),
+                    (BlankLine, child("", &mut [])),
+                    (Info, child("d", &mut []))
+                ])),
+                (BlankLine, child("", &mut [])),
+                (Info, child("e", &mut [])),
+                (BlankLine, child("", &mut []))
+            ]),
+            tree
+        );
+        assert_eq!(doc, tree.to_text_with(TextMode::PreserveOriginal));
+
+        // and stay so when edited
+        let mut tree = ItemTree::parse("the doc", "- a\n  - b\n\n  - c\n- d\n");
+        let c = tree.node_at(&[0, 2]).unwrap();
+        tree.remove_subtree(c);
+        assert_eq!(
+            child("the doc", &mut [
+                (Info, child("a", &mut [
+                    (Info, child("b", &mut []))
+                ])),
+                (BlankLine, child("", &mut [])),
+                (Info, child("d", &mut []))
+            ]),
+            tree
+        );
+        assert_eq!("- a\n  - b\n\n- d\n", tree.to_text_with(TextMode::PreserveOriginal));
+    }
+
+    #[test]
+    fn edits_keep_round_trip() {
+        let docs = [
+            "- a\n  - b\n\n  - c\n- d\n",
+            "- a\n\n  - b\n    - c\n\n\n  - d\n\n- e\n  - f\n\n",
+            "\n- a\n  ? b\n\n    - c\n\n      * d\n  - e\n\n\n- f\n"
+        ];
+        let mut seed: u64 = 1;
+        let mut random = |n: usize| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % n
+        };
+        for doc in &docs {
+            for _ in 0..200 {
+                let mut tree = ItemTree::parse("the doc", doc);
+                for _ in 0..6 {
+                    let ids: Vec<ItemId> = (1..tree.nodes.len()).filter(|id| !tree.is_removed(*id)).collect();
+                    if ids.is_empty() {
+                        break;
+                    }
+                    let id = ids[random(ids.len())];
+                    match random(5) {
+                        0 => { tree.indent(id); },
+                        1 => { tree.outdent(id); },
+                        2 => { tree.move_up(id); },
+                        3 => { tree.move_down(id); },
+                        _ => { tree.remove_subtree(id); }
+                    }
+                    for &mode in &[TextMode::Normalized, TextMode::PreserveOriginal] {
+                        let text = tree.to_text_with(mode);
+                        assert_eq!(tree, ItemTree::parse("the doc", &text), "{:?} edited into {:?}", doc, text);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn syntax_tags() {
+        assert_eq!((Verbatim(Some("sql".to_string())), ""), ItemKind::parse("|lang=sql"));
+        assert_eq!((Verbatim(Some("c++".to_string())), ""), ItemKind::parse("|lang=c++"));
+        // only a syntax when followed by lines of the block
+        assert_eq!((Verbatim(None), "rust"), ItemKind::parse("|rust"));
+        assert_eq!((Verbatim(None), " rust"), ItemKind::parse("| rust"));
+        assert_eq!((Verbatim(None), "{"), ItemKind::parse("|{"));
+        assert_eq!((Verbatim(None), ""), ItemKind::parse("|"));
+    }
+
+    #[test]
+    fn verbatim_blocks() {
+        let doc = "- query\n  |lang=sql\n  | select *\n  |\n  |     from items;  \n  - plain\n  | a\n\n  | b\n";
+        let tree = ItemTree::parse("the doc", doc);
+        assert_eq!(
+            child("the doc", &mut [
+                (Info, child("query", &mut [
+                    (Verbatim(Some("sql".to_string())), child("select *\n\n    from items;  ", &mut [])),
+                    (Info, child("plain", &mut [])),
+                    (Verbatim(None), child("a", &mut [])),
+                    (BlankLine, child("", &mut [])),
+                    (Verbatim(None), child("b", &mut []))
+                ]))
+            ]),
+            tree
+        );
+        assert_eq!(doc, tree.to_text_with(TextMode::PreserveOriginal));
+        assert_eq!("  - query\n    |sql\n    | select *\n    |\n    |     from items;  \n    - plain\n    | a\n\n    | b\n",
+                   tree.to_text());
+
+        let tree = ItemTree::parse("the doc", "- a\n  |rust\n  | fn main() {}\n  |c++\n");
+        assert_eq!(
+            child("the doc", &mut [
+                (Info, child("a", &mut [
+                    (Verbatim(Some("rust".to_string())), child("fn main() {}\nc++", &mut []))
+                ]))
+            ]),
+            tree
+        );
+
+        let mut tree = ItemTree::parse("the doc", "|rust\n|\n");
+        let block = tree.node_at(&[0]).unwrap();
+        assert_eq!("", tree.nodes[block].text);
+        tree.nodes[block].text = "fn main() {\n}".to_string();
+        assert_eq!("|rust\n| fn main() {\n| }\n", tree.to_text_with(TextMode::PreserveOriginal));
+    }
+
+    #[test]
+    fn verbatim_lines_before_syntaxes() {
+        // documents from before syntax tags keep their text
+        let docs = [
+            ("  |note\n", vec!["note"]),
+            ("  | a\n  |b\n  | c\n", vec!["a\nb\nc"]),
+            ("  |note\n  |more\n", vec!["note\nmore"]),
+            ("- x\n  |note\n  - y\n  |done\n", vec!["note", "done"])
+        ];
+        for &(doc, ref texts) in &docs {
+            let tree = ItemTree::parse("the doc", doc);
+            let verbatim: Vec<&str> = (1..tree.nodes.len())
+                .filter(|id| tree.nodes[*id].kind == Verbatim(None))
+                .map(|id| tree.nodes[id].text.as_str())
+                .collect();
+            assert_eq!(*texts, verbatim, "{:?}", doc);
+            assert_eq!(doc, tree.to_text_with(TextMode::PreserveOriginal));
+            assert_eq!(tree, ItemTree::parse("the doc", &tree.to_text()));
+        }
+        let tree = ItemTree::parse("the doc", "  |note\n");
+        assert_eq!("  | note\n", tree.to_text());
+        // a syntax can't be written as "|sql" right after another block
+        let tree = ItemTree::parse("the doc", "  | a\n  |lang=sql\n  | b\n");
+        assert_eq!("  | a\n  |lang=sql\n  | b\n", tree.to_text());
+    }
+
+    #[test]
+    fn add_child_at() {
+        let mut tree = ItemTree::parse("the doc", "  - a\n  - c\n");
+        let root = tree.root();
+        tree.add_child_at(root, 1, Item::leaf(Info, "b"));
+        assert_eq!("  - a\n  - b\n  - c\n", tree.to_text());
+    }
+
+    #[test]
+    fn move_nodes() {
+        let mut tree = ItemTree::parse("the doc", "  - a\n    - a1\n  - b\n  - c\n");
+        let root = tree.root();
+        let a = tree.node_at(&[0]).unwrap();
+        let a1 = tree.node_at(&[0, 0]).unwrap();
+        let c = tree.node_at(&[2]).unwrap();
+
+        assert!(!tree.move_node(a, a1, 0), "can't move below itself");
+        assert!(!tree.move_node(a, a, 0));
+        assert!(!tree.move_node(root, a, 0));
+
+        assert!(tree.move_node(c, a, 0));
+        assert_eq!("  - a\n    - c\n    - a1\n  - b\n", tree.to_text());
+        assert_eq!(Some(a), tree.parent(c));
+        assert!(tree.move_node(a, root, 10));
+        assert_eq!("  - b\n  - a\n    - c\n    - a1\n", tree.to_text());
+    }
+
+    #[test]
+    fn indent_outdent() {
+        let mut tree = ItemTree::parse("the doc", "  - a\n    - a1\n  - b\n    - b1\n");
+        let a = tree.node_at(&[0]).unwrap();
+        let b = tree.node_at(&[1]).unwrap();
+        let a1 = tree.node_at(&[0, 0]).unwrap();
+
+        assert!(!tree.indent(a), "no previous sibling");
+        assert!(!tree.outdent(a), "already at the top");
+        assert!(tree.indent(b));
+        assert_eq!("  - a\n    - a1\n    - b\n      - b1\n", tree.to_text());
+        assert!(tree.outdent(a1));
+        assert_eq!("  - a\n    - b\n      - b1\n  - a1\n", tree.to_text());
+    }
+
+    #[test]
+    fn move_up_down() {
+        let mut tree = ItemTree::parse("the doc", "  - a\n  - b\n    - b1\n");
+        let a = tree.node_at(&[0]).unwrap();
+        let b1 = tree.node_at(&[1, 0]).unwrap();
+        assert!(!tree.move_up(a));
+        assert!(!tree.move_down(b1));
+        assert!(tree.move_down(a));
+        assert_eq!("  - b\n    - b1\n  - a\n", tree.to_text());
+        assert!(tree.move_up(a));
+        assert_eq!("  - a\n  - b\n    - b1\n", tree.to_text());
+    }
+
+    #[test]
+    fn remove_subtree() {
+        let mut tree = ItemTree::parse("the doc", "  - a\n    ? a1\n      - a11\n  - b\n");
+        let a = tree.node_at(&[0]).unwrap();
+        let a1 = tree.node_at(&[0, 0]).unwrap();
+        let b = tree.node_at(&[1]).unwrap();
+        let root = tree.root();
+
+        assert!(!tree.remove_if_leaf(a1));
+        assert!(tree.remove_subtree(root).is_none());
+
+        let mut removed = tree.remove_subtree(a1).unwrap();
+        assert_eq!("  - a\n  - b\n", tree.to_text());
+        assert_eq!(Planned, removed.nodes[removed.root()].kind);
+        assert_eq!("  - a11\n", removed.to_text());
+        assert!(tree.is_removed(a1));
+        assert!(!tree.is_removed(a));
+        assert_eq!("b", tree.nodes[b].text, "ids of remaining nodes are kept");
+
+        let kind = removed.nodes[removed.root()].kind.clone();
+        tree.append(b, kind, &mut removed);
+        assert_eq!("  - a\n  - b\n    ? a1\n      - a11\n", tree.to_text());
+    }
+
+    #[test]
+    fn structural_equality() {
+        let mut tree = ItemTree::parse("the doc", "  - a\n  - b\n    - b1\n");
+        let a = tree.node_at(&[0]).unwrap();
+        tree.remove_subtree(a);
+        let b = tree.node_at(&[0]).unwrap();
+        tree.add_child_at(b, 0, Item::leaf(Doing, "b0"));
+        assert_eq!(ItemTree::parse("the doc", "  - b\n    * b0\n    - b1\n"), tree);
+        assert!(ItemTree::parse("the doc", "  - b\n    - b1\n    * b0\n") != tree);
+    }
+
+    #[test]
+    fn compact() {
+        let mut tree = ItemTree::parse("the doc", "  - a\n    - a1\n  - b\n    - b1\n");
+        let a = tree.node_at(&[0]).unwrap();
+        let b = tree.node_at(&[1]).unwrap();
+        let b1 = tree.node_at(&[1, 0]).unwrap();
+        let (b_key, b1_key) = (tree.key(b), tree.key(b1));
+        tree.remove_subtree(a);
+        assert_eq!(2, tree.removed_count());
+        let before = tree.clone();
+
+        let remap = tree.compact();
+        assert_eq!(3, tree.nodes.len());
+        assert_eq!(0, tree.removed_count());
+        assert_eq!(before, tree);
+        assert_eq!(None, remap.get(&a));
+        assert_eq!(Some(remap[&b]), tree.find(b_key));
+        assert_eq!(Some(remap[&b1]), tree.find(b1_key));
+        assert_eq!(Some(remap[&b]), tree.parent(remap[&b1]));
+    }
+
+    #[test]
+    fn keys() {
+        let mut tree = ItemTree::parse("the doc", "  - a\n    - a1\n  - b\n");
+        let a = tree.node_at(&[0]).unwrap();
+        let a1 = tree.node_at(&[0, 0]).unwrap();
+        let b = tree.node_at(&[1]).unwrap();
+        let a1_key = tree.key(a1);
+        assert!(tree.key(a) != a1_key);
+
+        let mut removed = tree.remove_subtree(a1).unwrap();
+        assert_eq!(None, tree.find(a1_key));
+        tree.append(b, Info, &mut removed);
+        let pasted = tree.node_at(&[1, 0]).unwrap();
+        assert_eq!(Some(pasted), tree.find(a1_key), "keys survive removal");
+
+        let mut copy = tree.clone();
+        tree.append(a, Info, &mut copy);
+        let keys: HashSet<NodeKey> = (0..tree.nodes.len())
+            .filter(|id| !tree.is_removed(*id))
+            .map(|id| tree.key(id))
+            .collect();
+        assert_eq!(tree.nodes.len() - tree.removed_count(), keys.len(), "appended keys are unique");
+    }
+
+    #[test]
+    fn adopt_keys() {
+        let old = ItemTree::parse("the doc", "  - a\n    ? a1\n  - b\n");
+        let mut new = ItemTree::parse("the doc", "  - new\n  - a\n    # a1\n");
+        new.adopt_keys(&old);
+        assert_eq!(old.key(old.node_at(&[0]).unwrap()), new.key(new.node_at(&[1]).unwrap()));
+        assert_eq!(old.key(old.node_at(&[0, 0]).unwrap()), new.key(new.node_at(&[1, 0]).unwrap()));
+        assert_eq!(None, old.find(new.key(new.node_at(&[0]).unwrap())));
+    }
+
     #[test]
     fn parse_slim_item() {
         assert_eq!(Item::leaf(Info, "myitem"), Item::parse("-myitem"));
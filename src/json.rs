@@ -0,0 +1,282 @@
+use failure::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de;
+use serde_json::{self, Value};
+use itemtree::{ItemTree, ItemId, Item, ItemKind};
+
+/// Increased when documents written by this version can't be read by earlier versions
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A document in the nested form:
+/// `{"schema_version": 1, "title": "Plans", "items": [{"kind": "planned", "text": "paint", "items": [...]}]}`,
+/// where verbatim items have a "syntax" (null, or left out, for plain text), and items
+/// without children have no "items"
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NestedDocument {
+    pub schema_version: u32,
+    pub title: String,
+    #[serde(default)]
+    pub items: Vec<NestedItem>
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NestedItem {
+    #[serde(flatten)]
+    pub kind: ItemKind,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<NestedItem>
+}
+
+/// A document in the flat form, mirroring `ItemTree`:
+/// `{"schema_version": 1, "nodes": [{"kind": "info", "text": "Plans", "children": [1]}, ...], "parents": [null, 0, ...]}`,
+/// where the first node is the root, with the title as its text
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FlatDocument {
+    pub schema_version: u32,
+    pub nodes: Vec<Item>,
+    pub parents: Vec<Option<ItemId>>
+}
+
+impl NestedDocument {
+    pub fn of(tree: &ItemTree) -> Self {
+        NestedDocument {
+            schema_version: SCHEMA_VERSION,
+            title: tree.title(),
+            items: nested_items(tree, tree.root())
+        }
+    }
+
+    pub fn to_tree(&self) -> Result<ItemTree, Error> {
+        check_version(self.schema_version)?;
+        let mut tree = ItemTree::new(&self.title);
+        let root = tree.root();
+        add_nested(&mut tree, root, &self.items);
+        Ok(tree)
+    }
+}
+
+fn nested_items(tree: &ItemTree, parent: ItemId) -> Vec<NestedItem> {
+    tree.nodes[parent].children_ids.iter()
+        .map(|&id| NestedItem {
+            kind: tree.nodes[id].kind.clone(),
+            text: tree.nodes[id].text.clone(),
+            items: nested_items(tree, id)
+        })
+        .collect()
+}
+
+fn add_nested(tree: &mut ItemTree, parent: ItemId, items: &[NestedItem]) {
+    for item in items {
+        let id = tree.add_child(parent, Item::leaf(item.kind.clone(), &item.text));
+        add_nested(tree, id, &item.items);
+    }
+}
+
+impl FlatDocument {
+    /// Removed nodes are left out, so the ids may differ from those of the tree
+    pub fn of(tree: &ItemTree) -> Self {
+        let mut tree = tree.clone();
+        tree.compact();
+        FlatDocument {
+            schema_version: SCHEMA_VERSION,
+            nodes: tree.nodes,
+            parents: tree.parents
+        }
+    }
+
+    /// Checks that the nodes and parents describe a tree
+    pub fn to_tree(self) -> Result<ItemTree, Error> {
+        check_version(self.schema_version)?;
+        let count = self.nodes.len();
+        ensure!(count > 0, "no root node");
+        ensure!(self.parents.len() == count, "{} nodes but {} parents", count, self.parents.len());
+        ensure!(self.parents[0].is_none(), "the root node has a parent");
+        for (id, node) in self.nodes.iter().enumerate() {
+            for &child in &node.children_ids {
+                ensure!(child < count && self.parents[child] == Some(id),
+                        "node {} has child {}, which doesn't have it as parent", id, child);
+            }
+            if let Some(parent) = self.parents[id] {
+                ensure!(parent < count && self.nodes[parent].children_ids.iter().filter(|&&child| child == id).count() == 1,
+                        "node {} has parent {}, which doesn't have it as child once", id, parent);
+            } else {
+                ensure!(id == 0, "node {} has no parent", id);
+            }
+        }
+        // children and parents agree, so only cycles apart from the root are left
+        let mut reachable = 0;
+        let mut stack = vec![0];
+        while let Some(id) = stack.pop() {
+            reachable += 1;
+            ensure!(reachable <= count, "the nodes form a cycle");
+            stack.extend(&self.nodes[id].children_ids);
+        }
+        ensure!(reachable == count, "{} nodes can't be reached from the root", count - reachable);
+        Ok(ItemTree::from_arena(self.nodes, self.parents))
+    }
+}
+
+fn check_version(version: u32) -> Result<(), Error> {
+    ensure!(version >= 1 && version <= SCHEMA_VERSION,
+            "unsupported schema version {}, expected at most {}", version, SCHEMA_VERSION);
+    Ok(())
+}
+
+/// Trees are serialized in the nested form
+impl Serialize for ItemTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        NestedDocument::of(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ItemTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        NestedDocument::deserialize(deserializer)?
+            .to_tree()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// The kind of an item as written, see `ItemKind`
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum KindName {
+    Planned,
+    Doing,
+    Done,
+    Blocked,
+    Info,
+    Blank,
+    BlankLine,
+    Verbatim
+}
+
+#[derive(Deserialize)]
+struct TaggedKind {
+    kind: KindName,
+    #[serde(default)]
+    syntax: Option<String>
+}
+
+/// Like the derived `Deserialize` for the adjacently tagged enum, except that
+/// the "syntax" of a verbatim item is optional
+impl<'de> Deserialize<'de> for ItemKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let tagged = TaggedKind::deserialize(deserializer)?;
+        Ok(match tagged.kind {
+            KindName::Planned => ItemKind::Planned,
+            KindName::Doing => ItemKind::Doing,
+            KindName::Done => ItemKind::Done,
+            KindName::Blocked => ItemKind::Blocked,
+            KindName::Info => ItemKind::Info,
+            KindName::Blank => ItemKind::Blank,
+            KindName::BlankLine => ItemKind::BlankLine,
+            KindName::Verbatim => ItemKind::Verbatim(tagged.syntax)
+        })
+    }
+}
+
+/// The tree in the nested form, indented
+pub fn to_json(tree: &ItemTree) -> String {
+    serde_json::to_string_pretty(&NestedDocument::of(tree))
+        .expect("documents can always be serialized")
+}
+
+/// The tree in the flat form, indented
+pub fn to_flat_json(tree: &ItemTree) -> String {
+    serde_json::to_string_pretty(&FlatDocument::of(tree))
+        .expect("documents can always be serialized")
+}
+
+/// Reads a document in either form. The title is used if the document has none.
+pub fn from_json(title: &str, content: &str) -> Result<ItemTree, Error> {
+    let value: Value = serde_json::from_str(content)?;
+    let mut tree = if value.get("nodes").is_some() {
+        serde_json::from_value::<FlatDocument>(value)?.to_tree()?
+    } else {
+        serde_json::from_value::<NestedDocument>(value)?.to_tree()?
+    };
+    if tree.title().is_empty() {
+        let root = tree.root();
+        tree.nodes[root].text = title.to_string();
+    }
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itemtree::ItemKind::*;
+
+    fn tree() -> ItemTree {
+        let mut tree = ItemTree::parse("Plans", "- home\n  ? paint\n# clean\n");
+        let root = tree.root();
+        tree.add_child(root, Item::leaf(Verbatim(Some("sql".to_string())), "select 1;"));
+        tree
+    }
+
+    #[test]
+    fn nested() {
+        let expected = r#"{"schema_version":1,"title":"Plans","items":[
+            {"kind":"info","text":"home","items":[{"kind":"planned","text":"paint"}]},
+            {"kind":"done","text":"clean"},
+            {"kind":"verbatim","syntax":"sql","text":"select 1;"}]}"#;
+        let expected: Value = serde_json::from_str(expected).unwrap();
+        assert_eq!(expected, serde_json::from_str::<Value>(&to_json(&tree())).unwrap());
+        assert_eq!(tree(), from_json("", &to_json(&tree())).unwrap());
+
+        // through serde on the tree itself
+        let serialized = serde_json::to_string(&tree()).unwrap();
+        assert_eq!(tree(), serde_json::from_str::<ItemTree>(&serialized).unwrap());
+    }
+
+    #[test]
+    fn flat() {
+        let mut tree = tree();
+        let root = tree.root();
+        let removed = tree.add_child(root, Item::leaf(BlankLine, ""));
+        tree.remove_subtree(removed);
+
+        let flat: Value = serde_json::from_str(&to_flat_json(&tree)).unwrap();
+        assert_eq!(5, flat["nodes"].as_array().unwrap().len(), "without the removed node");
+        assert_eq!(Value::Null, flat["parents"][0]);
+        assert_eq!(1, flat["parents"][2]);
+        assert_eq!("verbatim", flat["nodes"][4]["kind"]);
+        assert_eq!(tree, from_json("", &to_flat_json(&tree)).unwrap());
+    }
+
+    #[test]
+    fn hand_written() {
+        let nested = r#"{"schema_version": 1, "title": "Notes", "items": [
+            {"kind": "verbatim", "text": "x"},
+            {"kind": "verbatim", "syntax": null, "text": "y"},
+            {"kind": "verbatim", "syntax": "sql", "text": "select 1;"},
+            {"kind": "done", "text": "z"}]}"#;
+        let tree = from_json("", nested).unwrap();
+        let kinds: Vec<ItemKind> = tree.nodes[1..].iter().map(|item| item.kind.clone()).collect();
+        assert_eq!(vec![Verbatim(None), Verbatim(None), Verbatim(Some("sql".to_string())), Done], kinds);
+
+        let flat = r#"{"schema_version": 1, "nodes": [
+            {"kind": "info", "text": "Notes", "children": [1]},
+            {"kind": "verbatim", "text": "x"}], "parents": [null, 0]}"#;
+        assert_eq!(Verbatim(None), from_json("", flat).unwrap().nodes[1].kind);
+    }
+
+    #[test]
+    fn invalid() {
+        let newer = to_json(&tree()).replace("\"schema_version\": 1", "\"schema_version\": 2");
+        assert!(from_json("", &newer).is_err());
+        assert!(from_json("", "{\"title\": \"no version\"}").is_err());
+        assert!(from_json("", "{\"schema_version\": 1, \"title\": \"\", \"items\": [{\"kind\": \"someday\", \"text\": \"\"}]}").is_err());
+
+        let node = |children: &str| format!("{{\"kind\": \"info\", \"text\": \"\", \"children\": [{}]}}", children);
+        let flat = |nodes: &[String], parents: &str|
+            format!("{{\"schema_version\": 1, \"nodes\": [{}], \"parents\": [{}]}}", nodes.join(","), parents);
+        assert!(from_json("", &flat(&[node("1"), node("")], "null, 0")).is_ok());
+        assert!(from_json("", &flat(&[node("1"), node("")], "null")).is_err(), "missing parent");
+        assert!(from_json("", &flat(&[node("1"), node("")], "null, 1")).is_err(), "wrong parent");
+        assert!(from_json("", &flat(&[node("2"), node("")], "null, 0")).is_err(), "child out of range");
+        assert!(from_json("", &flat(&[node(""), node("2"), node("1")], "null, 2, 1")).is_err(), "cycle");
+    }
+}
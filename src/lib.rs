@@ -1,9 +1,37 @@
-#![recursion_limit="128"]
+#![recursion_limit="256"]
 
 #[macro_use]
 extern crate yew;
+#[macro_use]
 extern crate failure;
+extern crate regex;
+#[macro_use]
+extern crate stdweb;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 
 pub mod itemtree;
 pub mod view;
 pub mod storage;
+pub mod revisions;
+pub mod archive;
+pub mod markdown;
+pub mod opml;
+pub mod org;
+pub mod json;
+pub mod highlight;
+pub mod html;
+pub mod formats;
+pub mod browser_files;
+pub mod browser_storage;
+pub mod search;
+pub mod report;
+pub mod diary;
+pub mod library;
+pub mod history;
+pub mod diff;
+pub mod merge;
+pub mod sync;
+pub mod remote;
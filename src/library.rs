@@ -0,0 +1,83 @@
+/// What is known about a stored document, without loading it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DocumentInfo {
+    pub title: String,
+    /// Milliseconds since the unix epoch
+    pub modified: f64,
+    pub item_count: usize
+}
+
+/// The stored documents, kept next to them in storage since
+/// the storage can't be listed
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DocumentIndex {
+    pub documents: Vec<DocumentInfo>
+}
+
+impl DocumentIndex {
+    pub fn get(&self, title: &str) -> Option<&DocumentInfo> {
+        self.documents.iter().find(|doc| doc.title == title)
+    }
+
+    pub fn contains(&self, title: &str) -> bool {
+        self.get(title).is_some()
+    }
+
+    /// Adds the document, or replaces the info for a document with the same title
+    pub fn update(&mut self, info: DocumentInfo) {
+        match self.documents.iter().position(|doc| doc.title == info.title) {
+            Some(idx) => self.documents[idx] = info,
+            None => self.documents.push(info)
+        }
+    }
+
+    pub fn remove(&mut self, title: &str) -> Option<DocumentInfo> {
+        self.documents.iter()
+            .position(|doc| doc.title == title)
+            .map(|idx| self.documents.remove(idx))
+    }
+
+    /// Most recently modified first
+    pub fn by_modified(&self) -> Vec<&DocumentInfo> {
+        let mut documents: Vec<&DocumentInfo> = self.documents.iter().collect();
+        documents.sort_by(|a, b| b.modified.partial_cmp(&a.modified)
+            .unwrap_or(::std::cmp::Ordering::Equal)
+            .then_with(|| a.title.cmp(&b.title)));
+        documents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(title: &str, modified: f64) -> DocumentInfo {
+        DocumentInfo { title: title.to_string(), modified, item_count: 1 }
+    }
+
+    #[test]
+    fn update_and_remove() {
+        let mut index = DocumentIndex::default();
+        index.update(info("a", 1.0));
+        index.update(info("b", 2.0));
+        index.update(info("a", 3.0));
+        assert_eq!(2, index.documents.len());
+        assert_eq!(3.0, index.get("a").unwrap().modified);
+
+        assert_eq!(Some(info("b", 2.0)), index.remove("b"));
+        assert!(!index.contains("b"));
+        assert_eq!(None, index.remove("b"));
+    }
+
+    #[test]
+    fn newest_first() {
+        let mut index = DocumentIndex::default();
+        index.update(info("old", 1.0));
+        index.update(info("new", 5.0));
+        index.update(info("also new", 5.0));
+        let titles: Vec<&str> = index.by_modified().iter()
+            .map(|doc| doc.title.as_ref())
+            .collect();
+        assert_eq!(vec!["also new", "new", "old"], titles);
+    }
+}
@@ -3,14 +3,24 @@ extern crate taskigt;
 
 use yew::prelude::*;
 use yew::services::console::ConsoleService;
+use yew::services::dialog::DialogService;
+use yew::services::fetch::FetchService;
+use yew::services::storage::{StorageService, Area};
+use yew::services::timeout::TimeoutService;
 use taskigt::view::{Context, Model};
-use taskigt::storage::LocalDocumentStorage;
+use taskigt::browser_storage::WebStorage;
+use taskigt::remote::RemoteStorage;
 
 fn main() {
     yew::initialize();
     let context = Context {
         console: ConsoleService::new(),
-        storage: LocalDocumentStorage::new()
+        storage: Box::new(WebStorage::local()),
+        dialog: DialogService::new(),
+        remote: RemoteStorage::new(),
+        fetch: FetchService::new(),
+        timeout: TimeoutService::new(),
+        preferences: StorageService::new(Area::Local)
     };
     let app: App<_, Model> = App::new(context);
     app.mount_to_body();
@@ -0,0 +1,225 @@
+use itemtree::{ItemTree, ItemId, Item};
+use itemtree::ItemKind::*;
+
+const INDENT: &'static str = "  ";
+const FENCE: &'static str = "```";
+/// Markdown task lists only have done and not done, the other states are marked in the text
+const DOING_MARK: &'static str = "**Doing:** ";
+const BLOCKED_MARK: &'static str = "**Blocked:** ";
+
+/// The tree as a Markdown document: the title as a heading, items as nested `-` lists,
+/// tasks as task list items, and verbatim items as fenced code blocks
+pub fn to_markdown(tree: &ItemTree) -> String {
+    let mut out = format!("# {}\n\n", tree.title());
+    write_children(tree, tree.root(), 0, &mut out);
+    out
+}
+
+fn write_children(tree: &ItemTree, parent: ItemId, depth: usize, out: &mut String) {
+    let indent = INDENT.repeat(depth);
+    let children = &tree.nodes[parent].children_ids;
+    let mut idx = 0;
+    while idx < children.len() {
+        let item = &tree.nodes[children[idx]];
+        if let Verbatim(ref syntax) = item.kind {
+            out.push_str(&format!("{}{}{}\n", indent, FENCE, syntax.as_ref().map_or("", |syntax| syntax.as_str())));
+            for line in item.text.split('\n') {
+                out.push_str(&format!("{}{}\n", indent, line));
+            }
+            out.push_str(&format!("{}{}\n", indent, FENCE));
+            write_children(tree, children[idx], depth + 1, out);
+            idx += 1;
+            continue;
+        }
+        let line = match item.kind {
+            Planned => format!("- [ ] {}", item.text),
+            Doing => format!("- [ ] {}{}", DOING_MARK, item.text),
+            Blocked => format!("- [ ] {}{}", BLOCKED_MARK, item.text),
+            Done => format!("- [x] {}", item.text),
+            BlankLine => "".to_string(),
+            _ => format!("- {}", item.text)
+        };
+        if line.is_empty() {
+            out.push('\n');
+        } else {
+            out.push_str(&format!("{}{}\n", indent, line.trim_end()));
+        }
+        write_children(tree, children[idx], depth + 1, out);
+        idx += 1;
+    }
+}
+
+/// Reads a Markdown document. A level one heading first in the document is the title,
+/// other headings become top-level items, with the following lists below them.
+/// List items and paragraphs are nested by indentation, and blank lines are skipped.
+pub fn from_markdown(title: &str, content: &str) -> ItemTree {
+    let mut tree = ItemTree::new(title);
+    let root = tree.root();
+    // the heading the following items are placed below
+    let mut section = root;
+    // open list items, by the column of their marker
+    let mut open: Vec<(usize, ItemId)> = vec![];
+    // the column of the code block being read, and whether it has lines yet
+    let mut fence: Option<(usize, ItemId, bool)> = None;
+    let mut seen_content = false;
+
+    for line in content.lines() {
+        let column = line.len() - line.trim_start().len();
+        let trimmed = line.trim();
+
+        if let Some((fence_column, block, has_lines)) = fence.take() {
+            if trimmed.starts_with(FENCE) || trimmed.starts_with("~~~") {
+                continue;
+            }
+            let text = if column >= fence_column { &line[fence_column..] } else { line.trim_start() };
+            if has_lines {
+                tree.nodes[block].text.push('\n');
+            }
+            tree.nodes[block].text.push_str(text.trim_end());
+            fence = Some((fence_column, block, true));
+            continue;
+        }
+        if trimmed.is_empty() {
+            continue;
+        }
+        if trimmed.starts_with(FENCE) || trimmed.starts_with("~~~") {
+            let syntax = trimmed[3..].trim_start_matches(|c| c == '`' || c == '~')
+                .split_whitespace().next()
+                .map(|syntax| syntax.to_string());
+            open.retain(|&(open_column, _)| open_column < column);
+            let parent = open.last().map_or(section, |&(_, id)| id);
+            let block = tree.add_child(parent, Item::leaf(Verbatim(syntax), ""));
+            fence = Some((column, block, false));
+            seen_content = true;
+            continue;
+        }
+        if let Some((level, heading)) = heading(trimmed) {
+            if level == 1 && !seen_content {
+                tree.nodes[root].text = heading.to_string();
+            } else {
+                section = tree.add_child(root, Item::leaf(Info, heading));
+            }
+            open.clear();
+            seen_content = true;
+            continue;
+        }
+        seen_content = true;
+        open.retain(|&(open_column, _)| open_column < column);
+        let parent = open.last().map_or(section, |&(_, id)| id);
+        match list_item(trimmed) {
+            Some(text) => {
+                let id = tree.add_child(parent, task(text));
+                open.push((column, id));
+            },
+            None => {
+                // a paragraph, or a continuation of a list item's text
+                tree.add_child(parent, Item::leaf(Info, trimmed));
+            }
+        }
+    }
+    tree
+}
+
+/// The level and text of an ATX heading, e.g. "## Plans"
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    if level >= 1 && level <= 6 && (rest.is_empty() || rest.starts_with(' ')) {
+        Some((level, rest.trim().trim_end_matches('#').trim_end()))
+    } else {
+        None
+    }
+}
+
+/// The text after a bullet ("-", "*", "+") or number ("1." or "1)")
+fn list_item(line: &str) -> Option<&str> {
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    let marker = if digits > 0 {
+        match line[digits..].chars().next() {
+            Some('.') | Some(')') => digits + 1,
+            _ => return None
+        }
+    } else {
+        match line.chars().next() {
+            Some('-') | Some('*') | Some('+') => 1,
+            _ => return None
+        }
+    };
+    let rest = &line[marker..];
+    if rest.is_empty() {
+        Some(rest)
+    } else if rest.starts_with(' ') || rest.starts_with('\t') {
+        Some(rest.trim_start())
+    } else {
+        None
+    }
+}
+
+/// A list item, with the kind given by its task list checkbox
+fn task(text: &str) -> Item {
+    let (kind, text) = if text.starts_with("[ ] ") || text == "[ ]" {
+        let text = text[3..].trim_start();
+        if text.starts_with(DOING_MARK) {
+            (Doing, &text[DOING_MARK.len()..])
+        } else if text.starts_with(BLOCKED_MARK) {
+            (Blocked, &text[BLOCKED_MARK.len()..])
+        } else {
+            (Planned, text)
+        }
+    } else if text.starts_with("[x] ") || text.starts_with("[X] ") || text == "[x]" || text == "[X]" {
+        (Done, text[3..].trim_start())
+    } else {
+        (Info, text)
+    };
+    Item::leaf(kind, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itemtree::child;
+
+    #[test]
+    fn export() {
+        let mut tree = ItemTree::parse("Plans", "- home\n  ? paint\n  # clean\n\n* work\n  ! deploy\n");
+        let root = tree.root();
+        let work = tree.nodes[root].children_ids[2];
+        tree.add_child(work, Item::leaf(Verbatim(Some("sh".to_string())), "make deploy\n  --prod"));
+        assert_eq!("# Plans\n\n\
+                    - home\n  - [ ] paint\n  - [x] clean\n\n\
+                    - [ ] **Doing:** work\n  - [ ] **Blocked:** deploy\n  ```sh\n  make deploy\n    --prod\n  ```\n",
+                   to_markdown(&tree));
+    }
+
+    #[test]
+    fn import() {
+        let markdown = "# Plans\n\nSome notes\n\n## Home\n\n* [ ] paint\n    1. walls\n    2. [X] ceiling\n\n\
+                        ## Work\n- deploy\n  ```sql\n  select *\n    from servers;\n  ```\n";
+        let tree = from_markdown("notes.md", markdown);
+        assert_eq!(
+            child("Plans", &mut [
+                (Info, child("Some notes", &mut [])),
+                (Info, child("Home", &mut [
+                    (Planned, child("paint", &mut [
+                        (Info, child("walls", &mut [])),
+                        (Done, child("ceiling", &mut []))
+                    ]))
+                ])),
+                (Info, child("Work", &mut [
+                    (Info, child("deploy", &mut [
+                        (Verbatim(Some("sql".to_string())), child("select *\n  from servers;", &mut []))
+                    ]))
+                ]))
+            ]),
+            tree
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut tree = ItemTree::parse("Plans", "- home\n  ? paint\n    # walls\n* work\n  ! deploy\n- [not a task]\n");
+        let root = tree.root();
+        tree.add_child(root, Item::leaf(Verbatim(None), "plain\n\n  indented"));
+        assert_eq!(tree, from_markdown("", &to_markdown(&tree)));
+    }
+}
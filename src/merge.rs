@@ -0,0 +1,536 @@
+use itemtree::{ItemTree, ItemId, ItemKind, Item};
+use itemtree::ItemKind::*;
+use diff::{self, Change, Diff};
+use std::collections::{HashMap, HashSet};
+
+/// Text of the items marking a conflict. The children of such an item are the competing
+/// versions, ours first.
+pub const CONFLICT_TEXT: &'static str = "Merge conflict";
+pub const REMOVED_IN_OURS: &'static str = "Merge conflict: removed in ours, changed in theirs";
+pub const REMOVED_IN_THEIRS: &'static str = "Merge conflict: changed in ours, removed in theirs";
+
+pub struct Merge {
+    pub tree: ItemTree,
+    /// The conflict items of the merged tree
+    pub conflicts: Vec<ItemId>
+}
+
+/// Three-way merge of documents, see `merge`
+pub fn merge_text(title: &str, base: &str, ours: &str, theirs: &str) -> Merge {
+    merge(&ItemTree::parse(title, base),
+          &ItemTree::parse(title, ours),
+          &ItemTree::parse(title, theirs))
+}
+
+/// Applies the changes from `base` to `theirs` on top of `ours`.
+///
+/// Kinds and texts are merged separately, so one side can change the kind of an item
+/// while the other edits its text. Items added in theirs are placed after the same
+/// sibling as in theirs, unless we added the same item. Conflicts are:
+///  * both sides changing the kind or text of an item differently, giving a conflict
+///    item with our version (keeping the merged children) and their version of the item
+///  * one side removing an item the other side changed, added items below, or changed
+///    items below, giving a conflict item with the changed version of the subtree
+///
+/// Items below a removed item that both sides still have are kept, where the side
+/// that removed the item put them (e.g. below the item replacing a renamed heading).
+/// If both sides move an item, it stays where we moved it.
+pub fn merge(base: &ItemTree, ours: &ItemTree, theirs: &ItemTree) -> Merge {
+    let ours_diff = diff::diff(base, ours);
+    let theirs_diff = diff::diff(base, theirs);
+    let merger = Merger {
+        base, theirs,
+        base_to_ours: invert(&ours_diff),
+        base_to_theirs: invert(&theirs_diff),
+        theirs_to_base: theirs_diff.matches.clone(),
+        rescued: HashSet::new(),
+        theirs_to_result: HashMap::new(),
+        ours_touched: touched(ours, &ours_diff),
+        theirs_touched: touched(theirs, &theirs_diff),
+        ours_moved: moved(&ours_diff),
+        theirs_moved: moved(&theirs_diff),
+        result: ours.clone()
+    };
+    merger.merge()
+}
+
+struct Merger<'a> {
+    base: &'a ItemTree,
+    theirs: &'a ItemTree,
+    base_to_ours: HashMap<ItemId, ItemId>,
+    base_to_theirs: HashMap<ItemId, ItemId>,
+    theirs_to_base: HashMap<ItemId, ItemId>,
+    /// Items of base taken out of a subtree removed in theirs, to be placed as in theirs
+    rescued: HashSet<ItemId>,
+    /// Items added in theirs, copied into the result
+    theirs_to_result: HashMap<ItemId, ItemId>,
+    /// Items (of each side) that were added, edited or had their kind changed
+    ours_touched: HashSet<ItemId>,
+    theirs_touched: HashSet<ItemId>,
+    ours_moved: HashSet<ItemId>,
+    theirs_moved: HashSet<ItemId>,
+    /// Starts out as ours, ids of ours are ids of the result
+    result: ItemTree
+}
+
+impl<'a> Merger<'a> {
+    fn merge(mut self) -> Merge {
+        let mut conflicts = vec![];
+        let mut changed_both = vec![];
+        let mut removed_in_theirs = vec![];
+        let mut changed_after_removal = vec![];
+        // items removed in either side, or below such items
+        let mut gone: HashSet<ItemId> = HashSet::new();
+
+        for base_id in self.preorder_base() {
+            let parent_gone = self.base.parent(base_id).map_or(false, |parent| gone.contains(&parent));
+            if parent_gone && !self.kept_by_both(base_id) {
+                gone.insert(base_id);
+                continue;
+            }
+            match (self.base_to_ours.get(&base_id).cloned(), self.base_to_theirs.get(&base_id).cloned()) {
+                (Some(ours_id), Some(theirs_id)) => {
+                    if let Some((kind, text)) = self.merge_item(base_id, ours_id, theirs_id) {
+                        changed_both.push((ours_id, kind, text));
+                    }
+                },
+                (Some(ours_id), None) => {
+                    gone.insert(base_id);
+                    self.rescue_kept(ours_id, ours_id);
+                    if self.subtree_touched(&self.result, ours_id, &self.ours_touched) {
+                        removed_in_theirs.push(ours_id);
+                    } else {
+                        self.result.remove_subtree(ours_id);
+                    }
+                },
+                (None, Some(theirs_id)) => {
+                    gone.insert(base_id);
+                    if self.theirs_subtree_touched(theirs_id) {
+                        changed_after_removal.push((base_id, theirs_id));
+                    }
+                },
+                (None, None) => { gone.insert(base_id); }
+            }
+        }
+
+        // moved first, so added items are placed after their moved siblings, then again
+        // for the items moved below added items
+        self.move_as_theirs();
+        self.add_theirs();
+        self.move_as_theirs();
+
+        for (ours_id, kind, text) in changed_both {
+            let conflict = self.wrap_in_conflict(ours_id, CONFLICT_TEXT);
+            self.result.add_child(conflict, Item::leaf(kind, &text));
+            conflicts.push(conflict);
+        }
+        for ours_id in removed_in_theirs {
+            conflicts.push(self.wrap_in_conflict(ours_id, REMOVED_IN_THEIRS));
+        }
+        for (base_id, theirs_id) in changed_after_removal {
+            if let Some(conflict) = self.restore_as_conflict(base_id, theirs_id) {
+                conflicts.push(conflict);
+            }
+        }
+        Merge { tree: self.result, conflicts }
+    }
+
+    fn preorder_base(&self) -> Vec<ItemId> {
+        let mut ids = vec![];
+        collect_preorder(self.base, self.base.root(), &mut ids);
+        ids.remove(0);
+        ids
+    }
+
+    /// Takes their kind and text where only they changed it.
+    /// Returns their kind and text if they conflict with ours.
+    fn merge_item(&mut self, base_id: ItemId, ours_id: ItemId, theirs_id: ItemId) -> Option<(ItemKind, String)> {
+        let base = &self.base.nodes[base_id];
+        let theirs = &self.theirs.nodes[theirs_id];
+        let ours = &mut self.result.nodes[ours_id];
+        let kind = merge_value(&base.kind, &mut ours.kind, &theirs.kind);
+        let text = merge_value(&base.text, &mut ours.text, &theirs.text);
+        if kind && text {
+            None
+        } else {
+            Some((theirs.kind.clone(), theirs.text.clone()))
+        }
+    }
+
+    fn kept_by_both(&self, base_id: ItemId) -> bool {
+        self.base_to_ours.contains_key(&base_id) && self.base_to_theirs.contains_key(&base_id)
+    }
+
+    /// Whether an item of theirs is there in ours too
+    fn kept_in_ours(&self, theirs_id: ItemId) -> bool {
+        self.theirs_to_base.get(&theirs_id).map_or(false, |base_id| self.base_to_ours.contains_key(base_id))
+    }
+
+    /// Moves the items below `id` (of the result) that they still have out of the subtree
+    /// of `removed`, which they removed, to be placed as in theirs
+    fn rescue_kept(&mut self, removed: ItemId, id: ItemId) {
+        for child_id in self.result.nodes[id].children_ids.clone() {
+            let base_id = self.base_to_ours.iter()
+                .find(|&(_, ours_id)| *ours_id == child_id)
+                .map(|(base_id, _)| *base_id);
+            match base_id {
+                Some(base_id) if self.base_to_theirs.contains_key(&base_id) => {
+                    let (parent, pos) = self.position_in_result(removed);
+                    self.result.move_node(child_id, parent, pos + 1);
+                    self.rescued.insert(base_id);
+                },
+                _ => self.rescue_kept(removed, child_id)
+            }
+        }
+    }
+
+    fn position_in_result(&self, id: ItemId) -> (ItemId, usize) {
+        let parent = self.result.parent(id).expect("only the root has no parent");
+        let pos = self.result.nodes[parent].children_ids.iter().position(|child| *child == id).unwrap_or(0);
+        (parent, pos)
+    }
+
+    /// Whether they changed anything below an item we removed, not counting the items we
+    /// still have elsewhere
+    fn theirs_subtree_touched(&self, id: ItemId) -> bool {
+        !self.kept_in_ours(id) && (self.theirs_touched.contains(&id) ||
+            self.theirs.nodes[id].children_ids.iter().any(|child| self.theirs_subtree_touched(*child)))
+    }
+
+    fn subtree_touched(&self, tree: &ItemTree, id: ItemId, touched: &HashSet<ItemId>) -> bool {
+        let mut ids = vec![];
+        collect_preorder(tree, id, &mut ids);
+        ids.iter().any(|id| touched.contains(id))
+    }
+
+    /// The item of the result corresponding to an item of theirs
+    fn counterpart(&self, theirs_id: ItemId) -> Option<ItemId> {
+        if theirs_id == self.theirs.root() {
+            return Some(self.result.root());
+        }
+        self.theirs_to_result.get(&theirs_id).cloned().or_else(|| {
+            let base_id = self.base_to_theirs.iter()
+                .find(|&(_, theirs)| *theirs == theirs_id)
+                .map(|(base, _)| *base)?;
+            self.base_to_ours.get(&base_id).cloned()
+                .filter(|ours_id| !self.result.is_removed(*ours_id))
+        })
+    }
+
+    /// Position in `parent` of the result, after the closest preceding sibling
+    /// (in theirs) that is there
+    fn position_as_theirs(&self, theirs_id: ItemId, parent: ItemId) -> usize {
+        let theirs_parent = self.theirs.parent(theirs_id).expect("only the root has no parent");
+        let siblings = &self.theirs.nodes[theirs_parent].children_ids;
+        let pos = siblings.iter().position(|id| *id == theirs_id).unwrap_or(0);
+        siblings[..pos].iter().rev()
+            .filter_map(|sibling| self.counterpart(*sibling))
+            .filter_map(|sibling| self.result.nodes[parent].children_ids.iter().position(|id| *id == sibling))
+            .next()
+            .map_or(0, |sibling_pos| sibling_pos + 1)
+    }
+
+    /// Copies the items they added, below the items corresponding to their parents
+    fn add_theirs(&mut self) {
+        let mut ids = vec![];
+        collect_preorder(self.theirs, self.theirs.root(), &mut ids);
+        for theirs_id in ids {
+            let is_added = theirs_id != self.theirs.root() &&
+                !self.base_to_theirs.values().any(|id| *id == theirs_id);
+            if !is_added || self.theirs.nodes[theirs_id].kind == BlankLine {
+                continue;
+            }
+            let theirs_parent = self.theirs.parent(theirs_id).expect("only the root has no parent");
+            // no counterpart if removed in ours, then restored as a conflict
+            if let Some(parent) = self.counterpart(theirs_parent) {
+                let id = match self.added_by_both(theirs_id, parent) {
+                    Some(id) => id,
+                    None => {
+                        let pos = self.position_as_theirs(theirs_id, parent);
+                        let item = &self.theirs.nodes[theirs_id];
+                        self.result.add_child_at(parent, pos, Item::leaf(item.kind.clone(), &item.text))
+                    }
+                };
+                self.theirs_to_result.insert(theirs_id, id);
+            }
+        }
+    }
+
+    /// An item we added below `parent`, equal to the one they added
+    fn added_by_both(&self, theirs_id: ItemId, parent: ItemId) -> Option<ItemId> {
+        let item = &self.theirs.nodes[theirs_id];
+        self.result.nodes[parent].children_ids.iter()
+            .find(|id| {
+                let ours = &self.result.nodes[**id];
+                ours.kind == item.kind && ours.text == item.text &&
+                    !self.base_to_ours.values().any(|ours_id| ours_id == *id) &&
+                    !self.theirs_to_result.values().any(|result_id| result_id == *id)
+            })
+            .cloned()
+    }
+
+    /// Moves the items only they moved to where they are in theirs
+    fn move_as_theirs(&mut self) {
+        let mut moves: Vec<(ItemId, ItemId)> = self.base_to_theirs.iter()
+            .filter(|&(base_id, theirs_id)| self.theirs_moved.contains(theirs_id) || self.rescued.contains(base_id))
+            .filter_map(|(base_id, theirs_id)| {
+                let ours_id = self.base_to_ours.get(base_id)?;
+                let moved_by_us = self.ours_moved.contains(ours_id) && !self.rescued.contains(base_id);
+                if moved_by_us || self.result.is_removed(*ours_id) {
+                    None
+                } else {
+                    Some((*ours_id, *theirs_id))
+                }
+            })
+            .collect();
+        // in their document order, so preceding siblings are in place
+        moves.sort_by_key(|&(_, theirs_id)| self.theirs.path_of(theirs_id));
+        for (ours_id, theirs_id) in moves {
+            let theirs_parent = self.theirs.parent(theirs_id).expect("only the root has no parent");
+            if let Some(parent) = self.counterpart(theirs_parent) {
+                let current = self.result.nodes[parent].children_ids.iter().position(|id| *id == ours_id);
+                let mut pos = self.position_as_theirs(theirs_id, parent);
+                if current.map_or(false, |current| current < pos) {
+                    pos -= 1;
+                }
+                self.result.move_node(ours_id, parent, pos);
+            }
+        }
+    }
+
+    /// Replaces the item with a conflict item, having the item as its only child
+    fn wrap_in_conflict(&mut self, id: ItemId, text: &str) -> ItemId {
+        let (parent, pos) = self.position_in_result(id);
+        let conflict = self.result.add_child_at(parent, pos, Item::leaf(Blocked, text));
+        self.result.move_node(id, conflict, 0);
+        conflict
+    }
+
+    /// Puts a copy of their version of an item we removed where it was in base
+    fn restore_as_conflict(&mut self, base_id: ItemId, theirs_id: ItemId) -> Option<ItemId> {
+        let base_parent = self.base.parent(base_id)?;
+        let parent = if base_parent == self.base.root() {
+            self.result.root()
+        } else {
+            self.base_to_ours.get(&base_parent).cloned()?
+        };
+        let pos = self.position_as_theirs(theirs_id, parent);
+        let conflict = self.result.add_child_at(parent, pos, Item::leaf(Blocked, REMOVED_IN_OURS));
+        self.copy_theirs(theirs_id, conflict);
+        Some(conflict)
+    }
+
+    /// Copies a subtree of theirs, without the items we still have elsewhere
+    fn copy_theirs(&mut self, theirs_id: ItemId, parent: ItemId) {
+        let item = &self.theirs.nodes[theirs_id];
+        let copy = self.result.add_child(parent, Item::leaf(item.kind.clone(), &item.text));
+        for child_id in &self.theirs.nodes[theirs_id].children_ids {
+            if !self.kept_in_ours(*child_id) {
+                self.copy_theirs(*child_id, copy);
+            }
+        }
+    }
+}
+
+/// The items of base that both sides still have, but the merged tree doesn't, which
+/// calls for a look before trusting the merge
+pub fn lost_items(base: &ItemTree, ours: &ItemTree, theirs: &ItemTree, merged: &ItemTree) -> Vec<ItemId> {
+    let in_ours = invert(&diff::diff(base, ours));
+    let in_theirs = invert(&diff::diff(base, theirs));
+    let in_merged = invert(&diff::diff(base, merged));
+    let mut ids: Vec<ItemId> = in_ours.keys()
+        .filter(|id| in_theirs.contains_key(id) && !in_merged.contains_key(id) && **id != base.root())
+        .cloned()
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// Takes theirs if only they changed the value, and tells if there was no conflict
+fn merge_value<T: PartialEq + Clone>(base: &T, ours: &mut T, theirs: &T) -> bool {
+    if ours == theirs || base == theirs {
+        true
+    } else if base == ours {
+        *ours = theirs.clone();
+        true
+    } else {
+        false
+    }
+}
+
+fn invert(diff: &Diff) -> HashMap<ItemId, ItemId> {
+    diff.matches.iter().map(|(new, old)| (*old, *new)).collect()
+}
+
+fn touched(tree: &ItemTree, diff: &Diff) -> HashSet<ItemId> {
+    diff.changes.iter()
+        .filter_map(|change| match *change {
+            Change::Added { new } | Change::Edited { new, .. } | Change::KindChanged { new, .. } => Some(new),
+            _ => None
+        })
+        .filter(|id| tree.nodes[*id].kind != BlankLine)
+        .collect()
+}
+
+fn moved(diff: &Diff) -> HashSet<ItemId> {
+    diff.changes.iter()
+        .filter_map(|change| match *change {
+            Change::Moved { new, .. } => Some(new),
+            _ => None
+        })
+        .collect()
+}
+
+fn collect_preorder(tree: &ItemTree, id: ItemId, ids: &mut Vec<ItemId>) {
+    ids.push(id);
+    for child_id in &tree.nodes[id].children_ids {
+        collect_preorder(tree, *child_id, ids);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &'static str = "  - Plan\n    ? Design\n    ? Build\n  - Notes\n    - Old note\n";
+
+    fn merged(ours: &str, theirs: &str) -> (String, usize) {
+        let merge = merge_text("doc", BASE, ours, theirs);
+        (merge.tree.to_text(), merge.conflicts.len())
+    }
+
+    #[test]
+    fn unchanged() {
+        assert_eq!((BASE.to_string(), 0), merged(BASE, BASE));
+    }
+
+    #[test]
+    fn independent_changes() {
+        let ours = "  - Plan\n    # Design\n    ? Build\n    ? Ship\n  - Notes\n    - Old note\n";
+        let theirs = "  - Plan\n    ? Design it well\n    ? Build\n  - Notes\n    - Old note\n    - New note\n";
+        assert_eq!(("  - Plan\n    # Design it well\n    ? Build\n    ? Ship\n  - Notes\n    - Old note\n    - New note\n".to_string(), 0),
+                   merged(ours, theirs));
+    }
+
+    #[test]
+    fn added_by_both() {
+        let ours = "  - Plan\n    ? Design\n    ? Build\n    ? Ship\n  - Notes\n    - Old note\n";
+        let theirs = "  - Plan\n    ? Design\n    ? Build\n    ? Ship\n      - Soon\n  - Notes\n    - Old note\n";
+        assert_eq!((theirs.to_string(), 0), merged(ours, theirs));
+    }
+
+    #[test]
+    fn removed_and_moved() {
+        let ours = "  - Plan\n    ? Design\n    ? Build\n  - Notes\n";
+        let theirs = "  - Plan\n    ? Build\n    ? Design\n  - Notes\n    - Old note\n";
+        assert_eq!(("  - Plan\n    ? Build\n    ? Design\n  - Notes\n".to_string(), 0),
+                   merged(ours, theirs));
+
+        let theirs = "  - Plan\n    ? Design\n    ? Build\n      - Old note\n  - Notes\n";
+        assert_eq!(("  - Plan\n    ? Design\n    ? Build\n      - Old note\n  - Notes\n".to_string(), 0),
+                   merged(BASE, theirs));
+    }
+
+    #[test]
+    fn conflicting_edits() {
+        let ours = "  - Plan\n    # Design\n    ? Build\n  - Notes\n    - Old note\n";
+        let theirs = "  - Plan\n    ! Design\n    ? Build\n  - Notes\n    - Old note\n";
+        assert_eq!(("  - Plan\n    ! Merge conflict\n      # Design\n      ! Design\n    ? Build\n  - Notes\n    - Old note\n".to_string(), 1),
+                   merged(ours, theirs));
+    }
+
+    #[test]
+    fn removed_and_changed() {
+        let ours = "  - Plan\n    ? Design\n    ? Build\n";
+        let theirs = "  - Plan\n    ? Design\n    ? Build\n  - Notes\n    - Old note\n    - New note\n";
+        assert_eq!(("  - Plan\n    ? Design\n    ? Build\n  ! Merge conflict: removed in ours, changed in theirs\n    - Notes\n      - Old note\n      - New note\n".to_string(), 1),
+                   merged(ours, theirs));
+        assert_eq!(("  - Plan\n    ? Design\n    ? Build\n  ! Merge conflict: changed in ours, removed in theirs\n    - Notes\n      - Old note\n      - New note\n".to_string(), 1),
+                   merged(theirs, ours));
+    }
+
+    #[test]
+    fn renamed_parent() {
+        let base = "  - Plans\n    ? Design\n    ? Build\n  - Notes\n";
+        let renamed = "  - Roadmap for Q3\n    ? Design\n    ? Build\n  - Notes\n";
+        let merge = merge_text("d", base, base, renamed);
+        assert_eq!((renamed.to_string(), 0), (merge.tree.to_text(), merge.conflicts.len()));
+        let merge = merge_text("d", base, renamed, base);
+        assert_eq!((renamed.to_string(), 0), (merge.tree.to_text(), merge.conflicts.len()));
+
+        // and a child edited on the other side
+        let edited = "  - Plans\n    ? Design\n    # Build\n  - Notes\n";
+        let merge = merge_text("d", base, edited, renamed);
+        assert_eq!(("  - Roadmap for Q3\n    ? Design\n    # Build\n  - Notes\n".to_string(), 0),
+                   (merge.tree.to_text(), merge.conflicts.len()));
+        let merge = merge_text("d", base, renamed, edited);
+        assert_eq!(("  - Roadmap for Q3\n    ? Design\n    # Build\n  - Notes\n".to_string(), 0),
+                   (merge.tree.to_text(), merge.conflicts.len()));
+
+        // or added below the renamed item, which counts as edited rather than removed
+        let added = "  - Plans\n    ? Design\n    ? Build\n    ? Ship\n  - Notes\n";
+        let merge = merge_text("d", base, added, renamed);
+        assert_eq!(("  - Roadmap for Q3\n    ? Design\n    ? Build\n    ? Ship\n  - Notes\n".to_string(), 0),
+                   (merge.tree.to_text(), merge.conflicts.len()));
+    }
+
+    #[test]
+    fn lost() {
+        let base = ItemTree::parse("d", BASE);
+        let ours = ItemTree::parse("d", "  - Plan\n    ? Design\n    ? Build\n  - Notes\n");
+        let theirs = ItemTree::parse("d", "  - Plan\n    ? Design\n  - Notes\n    - Old note\n");
+        assert!(lost_items(&base, &ours, &theirs, &merge(&base, &ours, &theirs).tree).is_empty());
+        // removed on either side isn't lost
+        let merged = ItemTree::parse("d", "  - Plan\n  - Notes\n");
+        let design = base.node_at(&[0, 0]).unwrap();
+        assert_eq!(vec![design], lost_items(&base, &ours, &theirs, &merged));
+    }
+
+    /// Applies pseudo random edits, as in the editor. Blank lines aren't merged, so none are added.
+    fn edited(base: &ItemTree, seed: &mut u64) -> ItemTree {
+        let mut tree = base.clone();
+        let mut next = |bound: usize| {
+            *seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (*seed >> 33) as usize % bound
+        };
+        let texts = ["Roadmap for Q3", "Design", "Ship it", "x", "Build more", "Notes"];
+        for _ in 0..next(4) + 1 {
+            let ids: Vec<ItemId> = (1..tree.nodes.len()).filter(|id| !tree.is_removed(*id)).collect();
+            if ids.is_empty() {
+                let root = tree.root();
+                tree.add_child(root, Item::leaf(Planned, texts[next(texts.len())]));
+                continue;
+            }
+            let id = ids[next(ids.len())];
+            match next(8) {
+                0 => tree.nodes[id].text = texts[next(texts.len())].to_string(),
+                1 => tree.nodes[id].kind = [Info, Planned, Doing, Done][next(4)].clone(),
+                2 => { tree.add_child_at(id, next(3), Item::leaf(Info, texts[next(texts.len())])); },
+                3 => { tree.remove_subtree(id); },
+                4 => { tree.indent(id); },
+                5 => { tree.outdent(id); },
+                6 => { tree.move_up(id); },
+                _ => { tree.move_down(id); }
+            }
+        }
+        tree
+    }
+
+    #[test]
+    fn one_sided_changes() {
+        let bases = [BASE, "  - Plans\n    ? Design\n    ? Build\n  - Notes\n", "  * a\n    - b\n      - c\n  - d\n"];
+        let mut seed = 7;
+        for base in bases.iter() {
+            let base = ItemTree::parse("doc", base);
+            for _ in 0..300 {
+                let changed = edited(&base, &mut seed);
+                let result = merge(&base, &base, &changed);
+                assert_eq!(changed.to_text(), result.tree.to_text(), "theirs of {:?}", base.to_text());
+                assert!(result.conflicts.is_empty());
+                let result = merge(&base, &changed, &base);
+                assert_eq!(changed.to_text(), result.tree.to_text(), "ours of {:?}", base.to_text());
+                assert!(result.conflicts.is_empty());
+            }
+        }
+    }
+}
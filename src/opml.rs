@@ -0,0 +1,311 @@
+use failure::Error;
+use itemtree::{ItemTree, ItemId, Item, ItemKind};
+use itemtree::ItemKind::*;
+
+/// The attribute keeping the `ItemKind`, prefixed by '_' like the attributes of other outliners
+const KIND_ATTRIBUTE: &'static str = "_kind";
+const SYNTAX_ATTRIBUTE: &'static str = "_syntax";
+/// Marks completed items in e.g. Workflowy
+const COMPLETE_ATTRIBUTE: &'static str = "_complete";
+
+/// The tree as an OPML 2.0 document, an `<outline>` per item
+pub fn to_opml(tree: &ItemTree) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<opml version=\"2.0\">\n");
+    out.push_str("  <head>\n");
+    out.push_str(&format!("    <title>{}</title>\n", escape(&tree.title())));
+    out.push_str("  </head>\n");
+    out.push_str("  <body>\n");
+    for &child_id in &tree.nodes[tree.root()].children_ids {
+        write_outline(tree, child_id, 2, &mut out);
+    }
+    out.push_str("  </body>\n");
+    out.push_str("</opml>\n");
+    out
+}
+
+fn write_outline(tree: &ItemTree, id: ItemId, depth: usize, out: &mut String) {
+    let item = &tree.nodes[id];
+    let indent = "  ".repeat(depth);
+    out.push_str(&format!("{}<outline text=\"{}\"", indent, escape(&item.text)));
+    if item.kind != Info {
+        out.push_str(&format!(" {}=\"{}\"", KIND_ATTRIBUTE, item.kind.name()));
+    }
+    if let Verbatim(Some(ref syntax)) = item.kind {
+        out.push_str(&format!(" {}=\"{}\"", SYNTAX_ATTRIBUTE, escape(syntax)));
+    }
+    if item.kind == Done {
+        out.push_str(&format!(" {}=\"true\"", COMPLETE_ATTRIBUTE));
+    }
+    if item.children_ids.is_empty() {
+        out.push_str("/>\n");
+        return;
+    }
+    out.push_str(">\n");
+    for &child_id in &item.children_ids {
+        write_outline(tree, child_id, depth + 1, out);
+    }
+    out.push_str(&format!("{}</outline>\n", indent));
+}
+
+fn kind_of(name: &str, syntax: Option<String>) -> Option<ItemKind> {
+    match name {
+        "planned" => Some(Planned),
+        "doing" => Some(Doing),
+        "done" => Some(Done),
+        "blocked" => Some(Blocked),
+        "info" => Some(Info),
+        "blank" => Some(Blank),
+        "blank-line" => Some(BlankLine),
+        "verbatim" => Some(Verbatim(syntax)),
+        _ => None
+    }
+}
+
+/// Reads an OPML document. Outlines from other outliners become `Info` items,
+/// or `Done` if marked complete. The title is used if the document has none.
+pub fn from_opml(title: &str, content: &str) -> Result<ItemTree, Error> {
+    let mut tree = ItemTree::new(title);
+    // the open elements, with the item of each open outline
+    let mut open: Vec<(String, Option<ItemId>)> = vec![];
+    let mut seen_root = false;
+
+    for token in tokenize(content)? {
+        match token {
+            Token::Start(name, attributes, empty) => {
+                if open.is_empty() {
+                    ensure!(name == "opml" && !seen_root, "not an OPML document, found <{}>", name);
+                    seen_root = true;
+                }
+                let item = if name == "outline" && open.iter().any(|&(ref open, _)| open == "body") {
+                    let parent = open.iter().rev()
+                        .filter_map(|&(_, item)| item)
+                        .next()
+                        .unwrap_or(tree.root());
+                    Some(tree.add_child(parent, outline_item(&attributes)))
+                } else {
+                    None
+                };
+                if !empty {
+                    open.push((name, item));
+                }
+            },
+            Token::End(name) => {
+                match open.pop() {
+                    Some((ref open_name, _)) if *open_name == name => {},
+                    Some((open_name, _)) => bail!("</{}> closes <{}>", name, open_name),
+                    None => bail!("</{}> closes nothing", name)
+                }
+            },
+            Token::Text(text) => {
+                let in_title = open.len() >= 2 && open[open.len() - 1].0 == "title" && open[open.len() - 2].0 == "head";
+                let text = text.trim();
+                if in_title && !text.is_empty() {
+                    let root = tree.root();
+                    tree.nodes[root].text = text.to_string();
+                }
+            }
+        }
+    }
+    ensure!(seen_root, "not an OPML document");
+    ensure!(open.is_empty(), "<{}> is not closed", open[open.len() - 1].0);
+    Ok(tree)
+}
+
+fn outline_item(attributes: &[(String, String)]) -> Item {
+    let attribute = |name: &str| attributes.iter()
+        .find(|&&(ref attribute, _)| attribute == name)
+        .map(|&(_, ref value)| value.clone());
+    let complete = attribute(COMPLETE_ATTRIBUTE).map_or(false, |complete| complete == "true");
+    let kind = attribute(KIND_ATTRIBUTE)
+        .and_then(|name| kind_of(&name, attribute(SYNTAX_ATTRIBUTE)))
+        .unwrap_or(if complete { Done } else { Info });
+    Item::leaf(kind, &attribute("text").unwrap_or_default())
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' => escaped.push_str("&#10;"),
+            '\t' => escaped.push_str("&#9;"),
+            _ => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> Result<String, Error> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        unescaped.push_str(&rest[..amp]);
+        let semicolon = rest[amp..].find(';')
+            .ok_or_else(|| format_err!("unterminated entity in '{}'", text))?;
+        let entity = &rest[amp + 1..amp + semicolon];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(::std::char::from_u32),
+            _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(::std::char::from_u32),
+            _ => None
+        };
+        unescaped.push(c.ok_or_else(|| format_err!("unknown entity '&{};'", entity))?);
+        rest = &rest[amp + semicolon + 1..];
+    }
+    unescaped.push_str(rest);
+    Ok(unescaped)
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    /// Name, attributes, and whether the element is empty (`<a/>`)
+    Start(String, Vec<(String, String)>, bool),
+    End(String),
+    Text(String)
+}
+
+/// Just enough XML for OPML: elements, attributes, text and entities.
+/// Declarations, comments and CDATA markers are skipped.
+fn tokenize(content: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = vec![];
+    let mut rest = content;
+    while !rest.is_empty() {
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            tokens.push(Token::Text(unescape(&rest[..end])?));
+            rest = &rest[end..];
+            continue;
+        }
+        let skipped = [("<?", "?>"), ("<!--", "-->"), ("<![CDATA[", "]]>"), ("<!", ">")].iter()
+            .find(|&&(start, _)| rest.starts_with(start))
+            .cloned();
+        if let Some((start, end)) = skipped {
+            let close = rest.find(end)
+                .ok_or_else(|| format_err!("unterminated {}", start))?;
+            if start == "<![CDATA[" {
+                tokens.push(Token::Text(rest[start.len()..close].to_string()));
+            }
+            rest = &rest[close + end.len()..];
+            continue;
+        }
+        let close = tag_end(rest).ok_or_else(|| format_err!("unterminated tag"))?;
+        let tag = &rest[1..close];
+        rest = &rest[close + 1..];
+        if tag.starts_with('/') {
+            tokens.push(Token::End(tag[1..].trim().to_string()));
+            continue;
+        }
+        let empty = tag.ends_with('/');
+        let tag = if empty { &tag[..tag.len() - 1] } else { tag };
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let name = tag[..name_end].to_string();
+        ensure!(!name.is_empty(), "tag without a name");
+        tokens.push(Token::Start(name, attributes(&tag[name_end..])?, empty));
+    }
+    Ok(tokens)
+}
+
+/// The position of the '>' ending the tag, which may be within quoted attribute values
+fn tag_end(text: &str) -> Option<usize> {
+    let mut quote = None;
+    for (idx, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return Some(idx),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn attributes(text: &str) -> Result<Vec<(String, String)>, Error> {
+    let mut attributes = vec![];
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let equals = rest.find('=')
+            .ok_or_else(|| format_err!("attribute without value in '{}'", text))?;
+        let name = rest[..equals].trim().to_string();
+        let value = rest[equals + 1..].trim_start();
+        let quote = value.chars().next()
+            .filter(|&c| c == '"' || c == '\'')
+            .ok_or_else(|| format_err!("unquoted value of {}", name))?;
+        let end = value[1..].find(quote)
+            .ok_or_else(|| format_err!("unterminated value of {}", name))?;
+        attributes.push((name, unescape(&value[1..end + 1])?));
+        rest = value[end + 2..].trim_start();
+    }
+    Ok(attributes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itemtree::child;
+
+    #[test]
+    fn export() {
+        let tree = ItemTree::parse("Plans & ideas", "- home\n  ? paint \"walls\"\n# <done>\n");
+        assert_eq!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                    <opml version=\"2.0\">\n  <head>\n    <title>Plans &amp; ideas</title>\n  </head>\n  <body>\n\
+                    \x20   <outline text=\"home\">\n\
+                    \x20     <outline text=\"paint &quot;walls&quot;\" _kind=\"planned\"/>\n\
+                    \x20   </outline>\n\
+                    \x20   <outline text=\"&lt;done&gt;\" _kind=\"done\" _complete=\"true\"/>\n\
+                    \x20 </body>\n</opml>\n",
+                   to_opml(&tree));
+    }
+
+    #[test]
+    fn import() {
+        let opml = r#"<?xml version="1.0"?>
+<!-- from another outliner -->
+<opml version="1.0"><head><title>Work</title></head>
+<body>
+  <outline text="deploy" _note="ignored">
+    <outline text='check &#x2713; &amp; ship' _complete="true" />
+    <outline text="select 1;" _kind="verbatim" _syntax="sql"></outline>
+    <outline text="unknown kind" _kind="someday"/>
+  </outline>
+</body>
+</opml>"#;
+        assert_eq!(
+            child("Work", &mut [
+                (Info, child("deploy", &mut [
+                    (Done, child("check \u{2713} & ship", &mut [])),
+                    (Verbatim(Some("sql".to_string())), child("select 1;", &mut [])),
+                    (Info, child("unknown kind", &mut []))
+                ]))
+            ]),
+            from_opml("file", opml).unwrap()
+        );
+        assert_eq!("file", from_opml("file", "<opml><body/></opml>").unwrap().title());
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(from_opml("", "<html></html>").is_err());
+        assert!(from_opml("", "<opml><body>").is_err());
+        assert!(from_opml("", "<opml><body></opml>").is_err());
+        assert!(from_opml("", "<opml><body><outline text=\"a</body></opml>").is_err());
+        assert!(from_opml("", "<opml><body><outline text=\"&nbsp;\"/></body></opml>").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut tree = ItemTree::parse("T", "- a\n  * b\n\n  ! c\n    -\n- multi\n");
+        let root = tree.root();
+        tree.add_child(root, Item::leaf(Verbatim(None), "  x < y\ttab"));
+        tree.add_child(root, Item::leaf(Verbatim(Some("rust".to_string())), "line\nbreak"));
+        assert_eq!(tree, from_opml("", &to_opml(&tree)).unwrap());
+    }
+}
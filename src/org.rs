@@ -0,0 +1,239 @@
+use itemtree::{ItemTree, ItemId, Item, ItemKind};
+use itemtree::ItemKind::*;
+
+/// The todo keywords, and the kinds they stand for
+const KEYWORDS: [(&'static str, ItemKind); 4] = [("TODO", Planned), ("DOING", Doing), ("DONE", Done), ("WAITING", Blocked)];
+
+/// The tree as an Org document, with items as headlines, tasks with todo keywords,
+/// and verbatim blocks as source blocks. Org text belongs to the headline before it,
+/// so verbatim blocks placed after a sibling are read back as children of that sibling.
+pub fn to_org(tree: &ItemTree) -> String {
+    let mut out = format!("#+TITLE: {}\n\n", tree.title());
+    write_children(tree, tree.root(), 1, &mut out);
+    out
+}
+
+fn write_children(tree: &ItemTree, parent: ItemId, level: usize, out: &mut String) {
+    let children = &tree.nodes[parent].children_ids;
+    let mut idx = 0;
+    while idx < children.len() {
+        let item = &tree.nodes[children[idx]];
+        if let Verbatim(ref syntax) = item.kind {
+            match *syntax {
+                Some(ref syntax) => out.push_str(&format!("#+BEGIN_SRC {}\n", syntax)),
+                None => out.push_str("#+BEGIN_SRC\n")
+            }
+            out.push_str(&item.text);
+            out.push_str("\n#+END_SRC\n");
+            write_children(tree, children[idx], level + 1, out);
+            idx += 1;
+            continue;
+        }
+        if item.kind == BlankLine {
+            out.push('\n');
+        } else {
+            let keyword = KEYWORDS.iter()
+                .find(|&&(_, ref kind)| *kind == item.kind)
+                .map_or("".to_string(), |&(keyword, _)| format!("{} ", keyword));
+            let headline = format!("{} {}{}", "*".repeat(level), keyword, item.text);
+            out.push_str(headline.trim_end());
+            out.push('\n');
+        }
+        write_children(tree, children[idx], level + 1, out);
+        idx += 1;
+    }
+}
+
+/// Reads an Org document. Headlines and plain lists are nested by level and indentation,
+/// todo keywords and checkboxes give the kind, and source and example blocks become
+/// verbatim items. Everything else, such as properties, planning lines, timestamps and
+/// paragraphs, is kept as `Info` items below the headline or list item it belongs to.
+pub fn from_org(title: &str, content: &str) -> ItemTree {
+    let mut tree = ItemTree::new(title);
+    let root = tree.root();
+    // open headlines, by level
+    let mut headlines: Vec<(usize, ItemId)> = vec![];
+    // open list items below the innermost headline, by the column of their bullet
+    let mut list: Vec<(usize, ItemId)> = vec![];
+    // the column of the block being read, and whether it has lines yet
+    let mut block: Option<(usize, ItemId, bool)> = None;
+
+    for line in content.lines() {
+        let column = line.len() - line.trim_start().len();
+        let trimmed = line.trim();
+        let section = headlines.last().map_or(root, |&(_, id)| id);
+
+        if let Some((block_column, block_id, has_lines)) = block.take() {
+            if starts_with_keyword(trimmed, "#+END_") {
+                continue;
+            }
+            let text = if column >= block_column { &line[block_column..] } else { line.trim_start() };
+            if has_lines {
+                tree.nodes[block_id].text.push('\n');
+            }
+            tree.nodes[block_id].text.push_str(text.trim_end());
+            block = Some((block_column, block_id, true));
+            continue;
+        }
+        if trimmed.is_empty() {
+            continue;
+        }
+        if let Some((level, text)) = headline(line) {
+            headlines.retain(|&(open_level, _)| open_level < level);
+            list.clear();
+            let parent = headlines.last().map_or(root, |&(_, id)| id);
+            let id = tree.add_child(parent, keyword_item(text));
+            headlines.push((level, id));
+            continue;
+        }
+        if starts_with_keyword(trimmed, "#+TITLE:") {
+            tree.nodes[root].text = trimmed["#+TITLE:".len()..].trim().to_string();
+            continue;
+        }
+        list.retain(|&(list_column, _)| list_column < column);
+        let parent = list.last().map_or(section, |&(_, id)| id);
+        if starts_with_keyword(trimmed, "#+BEGIN_") {
+            let syntax = if starts_with_keyword(trimmed, "#+BEGIN_SRC") {
+                trimmed["#+BEGIN_SRC".len()..].split_whitespace().next().map(|syntax| syntax.to_string())
+            } else {
+                None
+            };
+            let block_id = tree.add_child(parent, Item::leaf(Verbatim(syntax), ""));
+            block = Some((column, block_id, false));
+            continue;
+        }
+        if trimmed.eq_ignore_ascii_case(":PROPERTIES:") || trimmed.eq_ignore_ascii_case(":END:") {
+            // the properties themselves are kept
+            continue;
+        }
+        match list_item(trimmed) {
+            Some(text) => {
+                let id = tree.add_child(parent, checkbox_item(text));
+                list.push((column, id));
+            },
+            None => {
+                tree.add_child(parent, Item::leaf(Info, trimmed));
+            }
+        }
+    }
+    tree
+}
+
+/// Org keywords are case insensitive
+fn starts_with_keyword(line: &str, keyword: &str) -> bool {
+    line.get(..keyword.len()).map_or(false, |start| start.eq_ignore_ascii_case(keyword))
+}
+
+/// The level and text of a headline, e.g. "** TODO Plans"
+fn headline(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '*').count();
+    let rest = &line[level..];
+    if level > 0 && (rest.is_empty() || rest.starts_with(' ')) {
+        Some((level, rest.trim()))
+    } else {
+        None
+    }
+}
+
+fn keyword_item(text: &str) -> Item {
+    for &(keyword, ref kind) in KEYWORDS.iter() {
+        if text == keyword {
+            return Item::leaf(kind.clone(), "");
+        }
+        if text.starts_with(keyword) && text[keyword.len()..].starts_with(' ') {
+            return Item::leaf(kind.clone(), text[keyword.len()..].trim_start());
+        }
+    }
+    Item::leaf(Info, text)
+}
+
+/// The text after a bullet ("-", "+", or "*" when indented) or number ("1." or "1)")
+fn list_item(line: &str) -> Option<&str> {
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    let marker = if digits > 0 {
+        match line[digits..].chars().next() {
+            Some('.') | Some(')') => digits + 1,
+            _ => return None
+        }
+    } else {
+        match line.chars().next() {
+            Some('-') | Some('+') | Some('*') => 1,
+            _ => return None
+        }
+    };
+    let rest = &line[marker..];
+    if rest.is_empty() || rest.starts_with(' ') {
+        Some(rest.trim_start())
+    } else {
+        None
+    }
+}
+
+fn checkbox_item(text: &str) -> Item {
+    let kinds = [("[ ]", Planned), ("[-]", Doing), ("[X]", Done), ("[x]", Done)];
+    for &(checkbox, ref kind) in kinds.iter() {
+        if text == checkbox || (text.starts_with(checkbox) && text[checkbox.len()..].starts_with(' ')) {
+            return Item::leaf(kind.clone(), text[checkbox.len()..].trim_start());
+        }
+    }
+    Item::leaf(Info, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itemtree::child;
+
+    #[test]
+    fn export() {
+        let mut tree = ItemTree::parse("Plans", "- home\n  ? paint\n  # clean\n\n* work\n  ! deploy\n");
+        let root = tree.root();
+        let work = tree.nodes[root].children_ids[2];
+        tree.add_child(work, Item::leaf(Verbatim(Some("sh".to_string())), "make deploy\n  --prod"));
+        assert_eq!("#+TITLE: Plans\n\n\
+                    * home\n** TODO paint\n** DONE clean\n\n\
+                    * DOING work\n** WAITING deploy\n#+BEGIN_SRC sh\nmake deploy\n  --prod\n#+END_SRC\n",
+                   to_org(&tree));
+    }
+
+    #[test]
+    fn import() {
+        let org = "#+TITLE: Plans\n#+AUTHOR: me\n\n\
+                   * Home :house:\n\
+                   ** TODO paint\n   SCHEDULED: <2018-06-02 Sat>\n   :PROPERTIES:\n   :EFFORT: 2h\n   :END:\n\
+                   \x20  - [ ] walls\n     1. [X] kitchen\n   - [-] ceiling\n\
+                   ** WAITING fix roof\n\
+                   * DONE Work\n  Notes on <2018-05-30 Wed>\n  - deploy\n    #+begin_src sql\n    select *\n      from servers;\n    #+end_src\n";
+        assert_eq!(
+            child("Plans", &mut [
+                (Info, child("#+AUTHOR: me", &mut [])),
+                (Info, child("Home :house:", &mut [
+                    (Planned, child("paint", &mut [
+                        (Info, child("SCHEDULED: <2018-06-02 Sat>", &mut [])),
+                        (Info, child(":EFFORT: 2h", &mut [])),
+                        (Planned, child("walls", &mut [
+                            (Done, child("kitchen", &mut []))
+                        ])),
+                        (Doing, child("ceiling", &mut []))
+                    ])),
+                    (Blocked, child("fix roof", &mut []))
+                ])),
+                (Done, child("Work", &mut [
+                    (Info, child("Notes on <2018-05-30 Wed>", &mut [])),
+                    (Info, child("deploy", &mut [
+                        (Verbatim(Some("sql".to_string())), child("select *\n  from servers;", &mut []))
+                    ]))
+                ]))
+            ]),
+            from_org("notes.org", org)
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut tree = ItemTree::parse("Plans", "- home\n  ? paint\n    # walls\n* work\n  ! deploy\n- TODOs\n");
+        let root = tree.root();
+        tree.add_child_at(root, 0, Item::leaf(Verbatim(None), "plain\n\n  indented"));
+        assert_eq!(tree, from_org("", &to_org(&tree)));
+    }
+}
@@ -0,0 +1,74 @@
+use yew::services::storage::{StorageService, Area};
+use yew::services::fetch::{Request, Response};
+use yew::format::{Json, Nothing, Text};
+use failure::Error;
+use sync::{SyncState, RemoteDocument};
+
+const STATE_KEY: &'static str = "taskigt.sync:state";
+
+/// Requests to the document server (see the taskigt-server binary), and the
+/// sync state they depend on, kept in local storage
+pub struct RemoteStorage {
+    storage_service: StorageService,
+    pub state: SyncState
+}
+
+impl RemoteStorage {
+    pub fn new() -> Self {
+        let mut storage_service = StorageService::new(Area::Local);
+        let Json(state): Json<Result<SyncState, Error>> = storage_service.restore(STATE_KEY);
+        RemoteStorage {
+            storage_service,
+            state: state.unwrap_or_default()
+        }
+    }
+
+    pub fn store_state(&mut self) {
+        self.storage_service.store(STATE_KEY, Json(&self.state));
+    }
+
+    pub fn list_request(&self) -> Request<Nothing> {
+        Request::get(self.state.list_url())
+            .body(Nothing)
+            .expect("failed to build list request")
+    }
+
+    pub fn get_request(&self, title: &str) -> Request<Nothing> {
+        Request::get(self.state.url_of(title))
+            .body(Nothing)
+            .expect("failed to build get request")
+    }
+
+    /// Only succeeds if the server has the version we last synced
+    pub fn put_request(&self, title: &str, content: String) -> Request<Text> {
+        let mut request = Request::put(self.state.url_of(title));
+        request.header("Content-Type", "text/plain; charset=utf-8");
+        match self.state.synced(title) {
+            Some(synced) => request.header("If-Match", synced.etag.as_str()),
+            None => request.header("If-None-Match", "*")
+        };
+        request.body(Ok(content))
+            .expect("failed to build put request")
+    }
+
+    /// Only succeeds if the server has the version we last synced
+    pub fn delete_request(&self, title: &str) -> Request<Nothing> {
+        let mut request = Request::delete(self.state.url_of(title));
+        if let Some(synced) = self.state.synced(title) {
+            request.header("If-Match", synced.etag.as_str());
+        }
+        request.body(Nothing)
+            .expect("failed to build delete request")
+    }
+}
+
+pub fn etag_of(response: &Response<Text>) -> Option<String> {
+    response.headers().get("ETag")
+        .and_then(|etag| etag.to_str().ok())
+        .map(|etag| etag.to_string())
+}
+
+pub fn parse_list(response: Response<Text>) -> Result<Vec<RemoteDocument>, Error> {
+    let Json(documents) = Json::from(response.into_body());
+    documents
+}
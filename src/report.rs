@@ -0,0 +1,154 @@
+use itemtree::{ItemTree, ItemId, ItemKind};
+use itemtree::ItemKind::*;
+
+/// The task kinds, in the order they are reported
+pub const TASK_KINDS: [ItemKind; 4] = [Doing, Blocked, Planned, Done];
+
+/// Number of tasks of each kind
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KindCounts {
+    pub planned: usize,
+    pub doing: usize,
+    pub done: usize,
+    pub blocked: usize
+}
+
+impl KindCounts {
+    /// Counts the tasks below `node`, not including `node` itself
+    pub fn of_subtree(tree: &ItemTree, node: ItemId) -> KindCounts {
+        let mut counts = KindCounts::default();
+        counts.add_offspring(tree, node);
+        counts
+    }
+
+    fn add_offspring(&mut self, tree: &ItemTree, node: ItemId) {
+        for child_id in &tree.nodes[node].children_ids {
+            self.add(&tree.nodes[*child_id].kind);
+            self.add_offspring(tree, *child_id);
+        }
+    }
+
+    pub fn add(&mut self, kind: &ItemKind) {
+        match *kind {
+            Planned => self.planned += 1,
+            Doing => self.doing += 1,
+            Done => self.done += 1,
+            Blocked => self.blocked += 1,
+            _ => {}
+        }
+    }
+
+    pub fn get(&self, kind: &ItemKind) -> usize {
+        match *kind {
+            Planned => self.planned,
+            Doing => self.doing,
+            Done => self.done,
+            Blocked => self.blocked,
+            _ => 0
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.planned + self.doing + self.done + self.blocked
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReportEntry {
+    pub id: ItemId,
+    /// Texts of the ancestors, outermost first, excluding the title
+    pub breadcrumb: Vec<String>,
+    /// Tasks below this one
+    pub counts: KindCounts
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KindReport {
+    pub kind: ItemKind,
+    pub entries: Vec<ReportEntry>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    /// One section per task kind, in `TASK_KINDS` order
+    pub sections: Vec<KindReport>,
+    /// Tasks below each top level item
+    pub subtrees: Vec<(ItemId, KindCounts)>
+}
+
+impl Report {
+    pub fn section(&self, kind: &ItemKind) -> Option<&KindReport> {
+        self.sections.iter().find(|section| &section.kind == kind)
+    }
+}
+
+/// Collects all tasks of the tree, grouped by kind, in document order
+pub fn report(tree: &ItemTree) -> Report {
+    let mut sections: Vec<KindReport> = TASK_KINDS.iter()
+        .map(|kind| KindReport { kind: kind.clone(), entries: vec![] })
+        .collect();
+    collect(tree, tree.root(), &mut sections);
+
+    let subtrees = tree.nodes[tree.root()].children_ids.iter()
+        .filter(|id| tree.nodes[**id].kind != BlankLine)
+        .map(|id| (*id, KindCounts::of_subtree(tree, *id)))
+        .collect();
+
+    Report { sections, subtrees }
+}
+
+fn collect(tree: &ItemTree, node: ItemId, sections: &mut Vec<KindReport>) {
+    for child_id in &tree.nodes[node].children_ids {
+        let kind = &tree.nodes[*child_id].kind;
+        if let Some(section) = sections.iter_mut().find(|section| &section.kind == kind) {
+            section.entries.push(ReportEntry {
+                id: *child_id,
+                breadcrumb: breadcrumb(tree, *child_id),
+                counts: KindCounts::of_subtree(tree, *child_id)
+            });
+        }
+        collect(tree, *child_id, sections);
+    }
+}
+
+pub fn breadcrumb(tree: &ItemTree, id: ItemId) -> Vec<String> {
+    tree.ancestors(id).iter()
+        .map(|ancestor| tree.nodes[*ancestor].text.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &'static str = "  - Project\n    * Build it\n      # Design\n      ! Review\n    ? Ship it\n  ! Vacation approval\n";
+
+    #[test]
+    fn groups_by_kind() {
+        let tree = ItemTree::parse("doc", DOC);
+        let report = report(&tree);
+
+        let blocked = report.section(&Blocked).unwrap();
+        let texts: Vec<&str> = blocked.entries.iter()
+            .map(|entry| tree.nodes[entry.id].text.as_ref())
+            .collect();
+        assert_eq!(vec!["Review", "Vacation approval"], texts);
+        assert_eq!(vec!["Project", "Build it"], blocked.entries[0].breadcrumb);
+        assert!(blocked.entries[1].breadcrumb.is_empty());
+
+        let doing = report.section(&Doing).unwrap();
+        assert_eq!(1, doing.entries.len());
+        assert_eq!(KindCounts { done: 1, blocked: 1, ..KindCounts::default() },
+                   doing.entries[0].counts);
+    }
+
+    #[test]
+    fn subtree_counts() {
+        let tree = ItemTree::parse("doc", DOC);
+        let report = report(&tree);
+        assert_eq!(2, report.subtrees.len());
+        assert_eq!(4, report.subtrees[0].1.total());
+        assert_eq!(0, report.subtrees[1].1.total());
+        assert_eq!(5, KindCounts::of_subtree(&tree, tree.root()).total());
+    }
+}
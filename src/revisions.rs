@@ -0,0 +1,111 @@
+use std::cmp::Ordering;
+
+/// The most revisions kept per document
+pub const REVISION_LIMIT: usize = 20;
+/// Saves closer together than this (e.g. autosaves while typing) keep one revision
+pub const REVISION_INTERVAL_MS: f64 = 5.0 * 60.0 * 1000.0;
+
+/// An earlier version of a document
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Revision {
+    /// When this version was saved, in milliseconds since the unix epoch
+    pub saved: f64,
+    pub content: String
+}
+
+/// The earlier versions of a document, a ring of at most `REVISION_LIMIT`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Revisions {
+    /// Oldest first
+    revisions: Vec<Revision>
+}
+
+impl Revisions {
+    /// Keeps the version being replaced by a save, dropping the oldest revision
+    /// when there are too many
+    pub fn keep(&mut self, replaced: Revision) {
+        let recently_kept = self.revisions.last()
+            .map_or(false, |newest| newest.content == replaced.content ||
+                                    replaced.saved - newest.saved < REVISION_INTERVAL_MS);
+        if recently_kept {
+            return;
+        }
+        self.revisions.push(replaced);
+        if self.revisions.len() > REVISION_LIMIT {
+            self.revisions.remove(0);
+        }
+    }
+
+    pub fn oldest(&self) -> Option<&Revision> {
+        self.revisions.first()
+    }
+
+    /// Removes the oldest revision, to make room in a full storage
+    pub fn prune_oldest(&mut self) -> Option<Revision> {
+        if self.revisions.is_empty() {
+            None
+        } else {
+            Some(self.revisions.remove(0))
+        }
+    }
+
+    pub fn newest_first(&self) -> Vec<Revision> {
+        self.revisions.iter().rev().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.revisions.is_empty()
+    }
+}
+
+/// The title of the document with the oldest revision
+pub fn oldest_of<'a, I>(documents: I) -> Option<&'a str>
+    where I: IntoIterator<Item = (&'a str, &'a Revisions)>
+{
+    documents.into_iter()
+        .filter_map(|(title, revisions)| revisions.oldest().map(|oldest| (title, oldest.saved)))
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+        .map(|(title, _)| title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revision(saved: f64, content: &str) -> Revision {
+        Revision { saved, content: content.to_string() }
+    }
+
+    #[test]
+    fn keep_revisions() {
+        let mut revisions = Revisions::default();
+        revisions.keep(revision(0.0, "a"));
+        revisions.keep(revision(1000.0, "b"));
+        assert_eq!(vec![revision(0.0, "a")], revisions.newest_first(), "saved too soon after the last one");
+
+        revisions.keep(revision(REVISION_INTERVAL_MS, "a"));
+        assert_eq!(1, revisions.newest_first().len(), "same content as the last one");
+
+        for n in 1..(REVISION_LIMIT as u32 + 5) {
+            revisions.keep(revision(n as f64 * REVISION_INTERVAL_MS, &n.to_string()));
+        }
+        let kept = revisions.newest_first();
+        assert_eq!(REVISION_LIMIT, kept.len());
+        assert_eq!((REVISION_LIMIT + 4).to_string(), kept[0].content);
+        assert_eq!("5", revisions.oldest().unwrap().content);
+
+        assert_eq!("5", revisions.prune_oldest().unwrap().content);
+        assert_eq!(REVISION_LIMIT - 1, revisions.newest_first().len());
+    }
+
+    #[test]
+    fn oldest_document() {
+        let mut a = Revisions::default();
+        a.keep(revision(REVISION_INTERVAL_MS, "a"));
+        let mut b = Revisions::default();
+        b.keep(revision(0.0, "b"));
+        let empty = Revisions::default();
+        assert_eq!(Some("b"), oldest_of(vec![("a", &a), ("b", &b), ("c", &empty)]));
+        assert_eq!(None, oldest_of(vec![("c", &empty)]));
+    }
+}
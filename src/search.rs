@@ -0,0 +1,166 @@
+use itemtree::{ItemTree, ItemId, ItemKind};
+use itemtree::ItemKind::*;
+use regex::{Regex, RegexBuilder};
+use failure::Error;
+
+/// How the text of an item is matched
+#[derive(Clone, Debug)]
+pub enum TextMatcher {
+    Substring(String),
+    CaseInsensitive(String),
+    Regex(Regex)
+}
+
+impl TextMatcher {
+    pub fn regex(pattern: &str) -> Result<TextMatcher, Error> {
+        Ok(TextMatcher::Regex(Regex::new(pattern)?))
+    }
+
+    pub fn case_insensitive(text: &str) -> TextMatcher {
+        TextMatcher::CaseInsensitive(text.to_lowercase())
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        match *self {
+            TextMatcher::Substring(ref needle) => text.contains(needle.as_str()),
+            TextMatcher::CaseInsensitive(ref needle) => text.to_lowercase().contains(needle.as_str()),
+            TextMatcher::Regex(ref regex) => regex.is_match(text)
+        }
+    }
+}
+
+/// An item matches a query if it is of one of the kinds (any kind, if none are given)
+/// and all of the text matchers match its text
+#[derive(Clone, Debug, Default)]
+pub struct Query {
+    pub kinds: Vec<ItemKind>,
+    pub text: Vec<TextMatcher>
+}
+
+impl Query {
+    /// Parses a query of space separated terms:
+    /// * `kind:<name or bullet>`, e.g. `kind:blocked` or `kind:!`
+    /// * `text:<text>` or just `<text>`, case insensitive substring
+    /// * `case:<text>`, case sensitive substring
+    /// * `re:<regex>`, regular expression
+    /// * `ire:<regex>`, case insensitive regular expression
+    pub fn parse(query: &str) -> Result<Query, Error> {
+        let mut parsed = Query::default();
+        for term in query.split_whitespace() {
+            let (prefix, value) = match term.find(':') {
+                Some(idx) => (&term[..idx], &term[idx + 1..]),
+                None => ("text", term)
+            };
+            match prefix {
+                "kind" => parsed.kinds.push(kind_from_name(value)
+                    .ok_or_else(|| format_err!("unknown item kind '{}'", value))?),
+                "text" => parsed.text.push(TextMatcher::case_insensitive(value)),
+                "case" => parsed.text.push(TextMatcher::Substring(value.to_string())),
+                "re" => parsed.text.push(TextMatcher::regex(value)?),
+                "ire" => parsed.text.push(TextMatcher::Regex(
+                    RegexBuilder::new(value).case_insensitive(true).build()?)),
+                // not a known prefix, e.g. a time like 10:30
+                _ => parsed.text.push(TextMatcher::case_insensitive(term))
+            }
+        }
+        Ok(parsed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty() && self.text.is_empty()
+    }
+
+    pub fn is_match(&self, kind: &ItemKind, text: &str) -> bool {
+        let kind_matches = self.kinds.is_empty() || self.kinds.iter().any(|k| same_kind(k, kind));
+        kind_matches && self.text.iter().all(|matcher| matcher.is_match(text))
+    }
+}
+
+/// Verbatim items match regardless of syntax
+pub fn same_kind(a: &ItemKind, b: &ItemKind) -> bool {
+    match (a, b) {
+        (&Verbatim(_), &Verbatim(_)) => true,
+        _ => a == b
+    }
+}
+
+pub fn kind_from_name(name: &str) -> Option<ItemKind> {
+    match name.to_lowercase().as_ref() {
+        "planned" | "?" => Some(Planned),
+        "doing" | "*" => Some(Doing),
+        "done" | "#" => Some(Done),
+        "blocked" | "!" => Some(Blocked),
+        "info" | "-" => Some(Info),
+        "verbatim" | "|" => Some(Verbatim(None)),
+        _ => None
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchHit {
+    pub id: ItemId,
+    /// Outermost first, excluding the root
+    pub ancestors: Vec<ItemId>
+}
+
+/// All items matching the query, in document order. Blank items and the root never match.
+pub fn search(tree: &ItemTree, query: &Query) -> Vec<SearchHit> {
+    let mut hits = vec![];
+    search_rec(tree, tree.root(), query, &mut hits);
+    hits
+}
+
+fn search_rec(tree: &ItemTree, node: ItemId, query: &Query, hits: &mut Vec<SearchHit>) {
+    for child_id in &tree.nodes[node].children_ids {
+        let child = &tree.nodes[*child_id];
+        let is_blank = child.kind == Blank || child.kind == BlankLine;
+        if !is_blank && query.is_match(&child.kind, &child.text) {
+            hits.push(SearchHit { id: *child_id, ancestors: tree.ancestors(*child_id) });
+        }
+        search_rec(tree, *child_id, query, hits);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &'static str = "  - Release\n    ! Deploy to prod\n    * deploy to staging\n  ? Write docs\n";
+
+    fn texts(tree: &ItemTree, hits: &[SearchHit]) -> Vec<String> {
+        hits.iter().map(|hit| tree.nodes[hit.id].text.clone()).collect()
+    }
+
+    #[test]
+    fn substring() {
+        let tree = ItemTree::parse("doc", DOC);
+        let hits = search(&tree, &Query::parse("deploy").unwrap());
+        assert_eq!(vec!["Deploy to prod", "deploy to staging"], texts(&tree, &hits));
+        assert_eq!(vec![tree.node_at(&[0]).unwrap()], hits[0].ancestors);
+
+        let hits = search(&tree, &Query::parse("case:Deploy").unwrap());
+        assert_eq!(vec!["Deploy to prod"], texts(&tree, &hits));
+    }
+
+    #[test]
+    fn kind_filter() {
+        let tree = ItemTree::parse("doc", DOC);
+        let hits = search(&tree, &Query::parse("kind:blocked text:deploy").unwrap());
+        assert_eq!(vec!["Deploy to prod"], texts(&tree, &hits));
+
+        let hits = search(&tree, &Query::parse("kind:? kind:*").unwrap());
+        assert_eq!(vec!["deploy to staging", "Write docs"], texts(&tree, &hits));
+
+        assert!(Query::parse("kind:urgent").is_err());
+    }
+
+    #[test]
+    fn regex() {
+        let tree = ItemTree::parse("doc", DOC);
+        let hits = search(&tree, &Query::parse("re:^[A-Z]\\w+$").unwrap());
+        assert_eq!(vec!["Release"], texts(&tree, &hits));
+
+        let hits = search(&tree, &Query::parse("ire:^deploy").unwrap());
+        assert_eq!(2, hits.len());
+    }
+}
@@ -1,32 +1,326 @@
+use itemtree::ItemTree;
+use library::{DocumentIndex, DocumentInfo};
+use revisions::{Revision, Revisions};
+use sync::{encode_title, decode_title};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
 
-use yew::services::storage::{StorageService, Area};
-use failure::Error;
+#[derive(Debug, Fail, PartialEq)]
+pub enum StorageError {
+    #[fail(display = "no document named '{}'", _0)]
+    NotFound(String),
+    #[fail(display = "a document named '{}' already exists", _0)]
+    AlreadyExists(String),
+    #[fail(display = "'{}' could not be parsed: {}", _0, _1)]
+    Parse(String, String),
+    #[fail(display = "the storage is full, '{}' could not be saved", _0)]
+    QuotaExceeded(String),
+    #[fail(display = "the stored data of '{}' is corrupt: {}", _0, _1)]
+    Corrupt(String, String),
+    #[fail(display = "the storage failed: {}", _0)]
+    Unavailable(String)
+}
+
+impl StorageError {
+    fn from_io(title: &str, error: io::Error) -> StorageError {
+        match error.kind() {
+            io::ErrorKind::NotFound => StorageError::NotFound(title.to_string()),
+            io::ErrorKind::InvalidData => StorageError::Corrupt(title.to_string(), error.to_string()),
+            _ => StorageError::Unavailable(error.to_string())
+        }
+    }
+}
+
+/// Where documents are kept, by title. See `browser_storage` for the web implementations.
+pub trait DocumentStorage {
+    fn save(&mut self, title: &str, content: String) -> Result<(), StorageError>;
+
+    fn restore(&self, title: &str) -> Result<String, StorageError>;
+
+    /// The stored documents, most recently modified first
+    fn list(&self) -> Vec<DocumentInfo>;
+
+    fn delete(&mut self, title: &str) -> Result<(), StorageError>;
+
+    /// Earlier versions of the document, newest first. Storages that don't keep them have none.
+    fn revisions(&self, _title: &str) -> Vec<Revision> {
+        vec![]
+    }
+
+    fn contains(&self, title: &str) -> bool {
+        self.list().iter().any(|doc| doc.title == title)
+    }
+
+    /// Storages that keep revisions move them along with the document
+    fn rename(&mut self, title: &str, new_title: &str) -> Result<(), StorageError> {
+        self.duplicate(title, new_title)?;
+        self.delete(title)
+    }
 
-const BASE_KEY: &'static str = "taskigt.storage";
+    fn duplicate(&mut self, title: &str, new_title: &str) -> Result<(), StorageError> {
+        if self.contains(new_title) {
+            return Err(StorageError::AlreadyExists(new_title.to_string()));
+        }
+        let content = self.restore(title)?;
+        self.save(new_title, content)
+    }
+}
+
+pub fn document_info(title: &str, content: &str, modified: f64) -> DocumentInfo {
+    DocumentInfo {
+        title: title.to_string(),
+        modified,
+        item_count: ItemTree::parse(title, content).item_count()
+    }
+}
 
-pub struct LocalDocumentStorage {
-    storage_service: StorageService
+/// Keeps documents in memory only, e.g. for tests. As there is no clock on all
+/// platforms, modification times count the saves, unless given by `save_at`.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    documents: HashMap<String, String>,
+    index: DocumentIndex,
+    revisions: HashMap<String, Revisions>,
+    saves: f64
 }
 
-impl LocalDocumentStorage {
+impl MemoryStorage {
     pub fn new() -> Self {
-        LocalDocumentStorage {
-            storage_service: StorageService::new(Area::Local)
+        MemoryStorage::default()
+    }
+
+    /// Saves with the given modification time
+    pub fn save_at(&mut self, title: &str, content: String, modified: f64) {
+        let replaced = self.index.get(title).map(|info| info.modified)
+            .and_then(|saved| self.documents.get(title).map(|content| Revision { saved, content: content.clone() }));
+        if let Some(replaced) = replaced {
+            self.revisions.entry(title.to_string()).or_insert_with(Revisions::default).keep(replaced);
         }
+        self.index.update(document_info(title, &content, modified));
+        self.documents.insert(title.to_string(), content);
+    }
+
+    pub fn revisions_of(&self, title: &str) -> Option<&Revisions> {
+        self.revisions.get(title)
     }
 
-    pub fn save(&mut self, title: &str, content: String) {
-        let mut key = BASE_KEY.to_string();
-        key.push('.');
-        key.push_str(title);
-        self.storage_service.store(&key, Ok(content));
+    /// Replaces the revisions of a document, e.g. when loaded from elsewhere
+    pub fn set_revisions(&mut self, title: &str, revisions: Revisions) {
+        self.revisions.insert(title.to_string(), revisions);
     }
 
-    pub fn restore(&mut self, title: &str) -> Result<String, Error> {
-        let mut key = BASE_KEY.to_string();
-        key.push('.');
-        key.push_str(&title);
-        self.storage_service.restore(&key)
+    /// Adds a document loaded from elsewhere. If a document with the title was saved
+    /// since, the loaded version is older and is kept among its revisions, returning true.
+    pub fn load(&mut self, title: &str, content: String, modified: f64, revisions: Revisions) -> bool {
+        if !self.documents.contains_key(title) {
+            self.save_at(title, content, modified);
+            self.set_revisions(title, revisions);
+            return false;
+        }
+        let mut merged = revisions;
+        merged.keep(Revision { saved: modified, content });
+        if let Some(saved_since) = self.revisions.get(title) {
+            for revision in saved_since.newest_first().into_iter().rev() {
+                merged.keep(revision);
+            }
+        }
+        self.set_revisions(title, merged);
+        true
     }
 }
 
+impl DocumentStorage for MemoryStorage {
+    fn save(&mut self, title: &str, content: String) -> Result<(), StorageError> {
+        self.saves += 1.0;
+        let modified = self.saves;
+        self.save_at(title, content, modified);
+        Ok(())
+    }
+
+    fn restore(&self, title: &str) -> Result<String, StorageError> {
+        self.documents.get(title)
+            .cloned()
+            .ok_or_else(|| StorageError::NotFound(title.to_string()))
+    }
+
+    fn list(&self) -> Vec<DocumentInfo> {
+        self.index.by_modified().into_iter().cloned().collect()
+    }
+
+    fn delete(&mut self, title: &str) -> Result<(), StorageError> {
+        self.documents.remove(title);
+        self.index.remove(title);
+        self.revisions.remove(title);
+        Ok(())
+    }
+
+    fn revisions(&self, title: &str) -> Vec<Revision> {
+        self.revisions.get(title).map_or(vec![], |revisions| revisions.newest_first())
+    }
+
+    fn rename(&mut self, title: &str, new_title: &str) -> Result<(), StorageError> {
+        if self.contains(new_title) {
+            return Err(StorageError::AlreadyExists(new_title.to_string()));
+        }
+        let content = self.documents.remove(title)
+            .ok_or_else(|| StorageError::NotFound(title.to_string()))?;
+        self.documents.insert(new_title.to_string(), content);
+        if let Some(mut info) = self.index.remove(title) {
+            info.title = new_title.to_string();
+            self.index.update(info);
+        }
+        if let Some(revisions) = self.revisions.remove(title) {
+            self.revisions.insert(new_title.to_string(), revisions);
+        }
+        Ok(())
+    }
+}
+
+/// Keeps each document as a text file in a directory, named by the percent-encoded title
+/// (the layout used by the taskigt-server binary). Only for native builds.
+#[derive(Clone, Debug)]
+pub struct FileStorage {
+    dir: PathBuf
+}
+
+impl FileStorage {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        FileStorage { dir: dir.into() }
+    }
+
+    fn path_of(&self, title: &str) -> PathBuf {
+        self.dir.join(format!("{}.txt", encode_title(title)))
+    }
+}
+
+impl DocumentStorage for FileStorage {
+    fn save(&mut self, title: &str, content: String) -> Result<(), StorageError> {
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(self.path_of(title), content))
+            .map_err(|e| StorageError::from_io(title, e))
+    }
+
+    fn restore(&self, title: &str) -> Result<String, StorageError> {
+        fs::read_to_string(self.path_of(title))
+            .map_err(|e| StorageError::from_io(title, e))
+    }
+
+    fn list(&self) -> Vec<DocumentInfo> {
+        let mut index = DocumentIndex::default();
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return vec![]
+        };
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            let title = path.file_name()
+                .and_then(|name| name.to_str())
+                .filter(|name| name.ends_with(".txt"))
+                .and_then(|name| decode_title(&name[..name.len() - 4]));
+            let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0.0, |since| since.as_secs() as f64 * 1000.0 + since.subsec_millis() as f64);
+            if let (Some(title), Ok(content)) = (title, fs::read_to_string(&path)) {
+                index.update(document_info(&title, &content, modified));
+            }
+        }
+        index.by_modified().into_iter().cloned().collect()
+    }
+
+    fn contains(&self, title: &str) -> bool {
+        self.path_of(title).is_file()
+    }
+
+    fn delete(&mut self, title: &str) -> Result<(), StorageError> {
+        fs::remove_file(self.path_of(title))
+            .map_err(|e| StorageError::from_io(title, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use revisions::REVISION_INTERVAL_MS;
+    use std::env;
+
+    fn exercise(storage: &mut dyn DocumentStorage) {
+        storage.save("a", "- one\n".to_string()).unwrap();
+        storage.save("b", "- one\n- two\n".to_string()).unwrap();
+        assert_eq!("- one\n", storage.restore("a").unwrap());
+        assert_eq!(Err(StorageError::NotFound("c".to_string())), storage.restore("c"));
+        assert_eq!(2, storage.list().len());
+        assert_eq!(2, storage.list().iter().find(|doc| doc.title == "b").unwrap().item_count);
+
+        storage.rename("a", "c/d").unwrap();
+        assert!(!storage.contains("a"));
+        assert_eq!("- one\n", storage.restore("c/d").unwrap());
+        assert_eq!(Err(StorageError::AlreadyExists("c/d".to_string())), storage.duplicate("b", "c/d"));
+        assert_eq!(Err(StorageError::AlreadyExists("b".to_string())), storage.duplicate("b", "b"));
+
+        storage.delete("b").unwrap();
+        let titles: Vec<String> = storage.list().into_iter().map(|doc| doc.title).collect();
+        assert_eq!(vec!["c/d".to_string()], titles);
+    }
+
+    #[test]
+    fn memory_storage() {
+        let mut storage = MemoryStorage::new();
+        exercise(&mut storage);
+        storage.save("e", "".to_string()).unwrap();
+        assert_eq!("e", storage.list()[0].title, "most recently saved first");
+    }
+
+    #[test]
+    fn memory_storage_revisions() {
+        let mut storage = MemoryStorage::new();
+        storage.save_at("a", "- one\n".to_string(), 0.0);
+        assert!(storage.revisions("a").is_empty());
+        storage.save_at("a", "- two\n".to_string(), REVISION_INTERVAL_MS);
+        storage.save_at("a", "- three\n".to_string(), 2.0 * REVISION_INTERVAL_MS);
+        let contents: Vec<String> = storage.revisions("a").into_iter().map(|revision| revision.content).collect();
+        assert_eq!(vec!["- two\n".to_string(), "- one\n".to_string()], contents);
+        assert_eq!(REVISION_INTERVAL_MS, storage.revisions("a")[0].saved);
+
+        storage.rename("a", "b").unwrap();
+        assert!(storage.revisions("a").is_empty());
+        assert_eq!(2, storage.revisions("b").len(), "revisions are kept when renaming");
+        assert_eq!(2.0 * REVISION_INTERVAL_MS, storage.list()[0].modified);
+        assert_eq!(Err(StorageError::NotFound("a".to_string())), storage.rename("a", "c"));
+
+        storage.delete("b").unwrap();
+        assert!(storage.revisions("b").is_empty());
+    }
+
+    #[test]
+    fn load_after_save() {
+        let mut storage = MemoryStorage::new();
+        let mut revisions = Revisions::default();
+        revisions.keep(Revision { saved: 0.0, content: "- zero\n".to_string() });
+        assert!(!storage.load("a", "- one\n".to_string(), REVISION_INTERVAL_MS, revisions.clone()));
+        assert_eq!("- one\n", storage.restore("a").unwrap());
+        assert_eq!(1, storage.revisions("a").len());
+
+        // saved before the stored version was loaded
+        storage.save_at("b", "- new\n".to_string(), 3.0 * REVISION_INTERVAL_MS);
+        storage.save_at("b", "- newer\n".to_string(), 4.0 * REVISION_INTERVAL_MS);
+        assert!(storage.load("b", "- stored\n".to_string(), REVISION_INTERVAL_MS, revisions));
+        assert_eq!("- newer\n", storage.restore("b").unwrap());
+        let contents: Vec<String> = storage.revisions("b").into_iter().map(|revision| revision.content).collect();
+        assert_eq!(vec!["- new\n", "- stored\n", "- zero\n"], contents);
+    }
+
+    #[test]
+    fn file_storage() {
+        let dir = env::temp_dir().join(format!("taskigt-storage-test-{}", ::std::process::id()));
+        let mut storage = FileStorage::new(dir.clone());
+        exercise(&mut storage);
+        fs::write(dir.join("bad.txt"), b"\xff").unwrap();
+        match storage.restore("bad") {
+            Err(StorageError::Corrupt(..)) => {},
+            other => panic!("expected corrupt data, got {:?}", other)
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
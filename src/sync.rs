@@ -0,0 +1,233 @@
+use itemtree::{ItemTree, TextMode};
+use merge;
+
+/// A document as listed by the server
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RemoteDocument {
+    pub title: String,
+    pub etag: String
+}
+
+/// The version of a document both we and the server had at the last sync,
+/// used as the base when merging
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SyncedDocument {
+    pub title: String,
+    pub etag: String,
+    pub content: String
+}
+
+/// A change that has not reached the server yet
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PendingChange {
+    Push(String),
+    Delete(String)
+}
+
+impl PendingChange {
+    pub fn title(&self) -> &str {
+        match *self {
+            PendingChange::Push(ref title) | PendingChange::Delete(ref title) => title
+        }
+    }
+}
+
+/// Everything the client knows about the server, kept in local storage so
+/// changes made while offline are pushed later
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncState {
+    /// Base url of the document server, e.g. http://localhost:8090, empty if not syncing
+    pub url: String,
+    pub synced: Vec<SyncedDocument>,
+    /// Oldest first, at most one change per document
+    pub queue: Vec<PendingChange>
+}
+
+impl SyncState {
+    pub fn is_enabled(&self) -> bool {
+        !self.url.trim().is_empty()
+    }
+
+    pub fn synced(&self, title: &str) -> Option<&SyncedDocument> {
+        self.synced.iter().find(|doc| doc.title == title)
+    }
+
+    pub fn set_synced(&mut self, title: &str, etag: &str, content: &str) {
+        self.forget(title);
+        self.synced.push(SyncedDocument {
+            title: title.to_string(),
+            etag: etag.to_string(),
+            content: content.to_string()
+        });
+    }
+
+    pub fn forget(&mut self, title: &str) {
+        self.synced.retain(|doc| doc.title != title);
+    }
+
+    /// Queues the change, replacing any earlier change of the same document
+    pub fn enqueue(&mut self, change: PendingChange) {
+        self.queue.retain(|queued| queued.title() != change.title());
+        self.queue.push(change);
+    }
+
+    /// Removes the change from the queue, unless a newer change replaced it
+    pub fn complete(&mut self, change: &PendingChange) {
+        self.queue.retain(|queued| queued != change);
+    }
+
+    pub fn url_of(&self, title: &str) -> String {
+        format!("{}/{}", self.list_url(), encode_title(title))
+    }
+
+    pub fn list_url(&self) -> String {
+        let url = self.url.trim();
+        let url = if url.ends_with('/') { &url[..url.len() - 1] } else { url };
+        format!("{}/documents", url)
+    }
+}
+
+/// The outcome of bringing a local document up to date with the server
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reconciled {
+    pub content: String,
+    /// Whether the content has changes the server doesn't have, that can be pushed
+    /// right away
+    pub push: bool,
+    pub conflicts: usize,
+    /// Items both versions have but the merge lost. Such a merge is kept locally, and only
+    /// pushed when saved.
+    pub lost: usize
+}
+
+/// Combines a document fetched from the server with the local version, merging
+/// from the last synced version (or from an empty document, if never synced)
+pub fn reconcile(title: &str, base: Option<&str>, local: Option<&str>, remote: &str) -> Reconciled {
+    let take_remote = Reconciled { content: remote.to_string(), push: false, conflicts: 0, lost: 0 };
+    let local = match local {
+        Some(local) => local,
+        None => return take_remote
+    };
+    if local == remote || Some(local) == base {
+        return take_remote;
+    }
+    if Some(remote) == base {
+        return Reconciled { content: local.to_string(), push: true, conflicts: 0, lost: 0 };
+    }
+    let (base, local, remote) = (ItemTree::parse(title, base.unwrap_or("")), ItemTree::parse(title, local),
+                                 ItemTree::parse(title, remote));
+    let merged = merge::merge(&base, &local, &remote);
+    let lost = merge::lost_items(&base, &local, &remote, &merged.tree).len();
+    Reconciled {
+        // the formatting of the local version is kept
+        content: merged.tree.to_text_with(TextMode::PreserveOriginal),
+        push: lost == 0,
+        conflicts: merged.conflicts.len(),
+        lost
+    }
+}
+
+/// Entity tag of a document's content, quoted as in the ETag header
+pub fn etag(content: &str) -> String {
+    // 64 bit FNV-1a, stable across platforms and releases
+    let hash = content.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("\"{:016x}\"", hash)
+}
+
+/// Percent-encodes everything but ASCII letters, digits, '-', '_' and '~',
+/// making the title safe both in urls and as a file name
+pub fn encode_title(title: &str) -> String {
+    let mut encoded = String::new();
+    for byte in title.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' || byte == b'~' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+pub fn decode_title(encoded: &str) -> Option<String> {
+    let bytes = encoded.as_bytes();
+    let mut decoded = vec![];
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if bytes[pos] == b'%' {
+            let hex = encoded.get(pos + 1..pos + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            pos += 3;
+        } else {
+            decoded.push(bytes[pos]);
+            pos += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn titles() {
+        let title = "Plans 2018/Q3: ½ done.";
+        let encoded = encode_title(title);
+        assert_eq!("Plans%202018%2FQ3%3A%20%C2%BD%20done%2E", encoded);
+        assert_eq!(Some(title.to_string()), decode_title(&encoded));
+        assert_eq!(None, decode_title("bad%2"));
+        assert_eq!(None, decode_title("%FF"));
+    }
+
+    #[test]
+    fn etags() {
+        assert_eq!(etag("- a\n"), etag("- a\n"));
+        assert!(etag("- a\n") != etag("- b\n"));
+        assert_eq!(18, etag("").len());
+    }
+
+    #[test]
+    fn queue() {
+        let mut state = SyncState::default();
+        state.enqueue(PendingChange::Push("a".to_string()));
+        state.enqueue(PendingChange::Push("b".to_string()));
+        state.enqueue(PendingChange::Delete("a".to_string()));
+        assert_eq!(vec![PendingChange::Push("b".to_string()), PendingChange::Delete("a".to_string())],
+                   state.queue);
+
+        state.complete(&PendingChange::Push("a".to_string()));
+        assert_eq!(2, state.queue.len(), "replaced changes are not completed");
+        state.complete(&PendingChange::Delete("a".to_string()));
+        assert_eq!(1, state.queue.len());
+    }
+
+    #[test]
+    fn urls() {
+        let state = SyncState { url: "http://localhost:8090/ ".to_string(), ..SyncState::default() };
+        assert_eq!("http://localhost:8090/documents/My%20items", state.url_of("My items"));
+        assert_eq!("http://localhost:8090/documents", state.list_url());
+    }
+
+    #[test]
+    fn reconcile_versions() {
+        let base = "  - a\n";
+        let changed = "  - a\n  - b\n";
+        assert_eq!(Reconciled { content: changed.to_string(), push: false, conflicts: 0, lost: 0 },
+                   reconcile("doc", Some(base), Some(base), changed));
+        assert_eq!(Reconciled { content: changed.to_string(), push: true, conflicts: 0, lost: 0 },
+                   reconcile("doc", Some(base), Some(changed), base));
+        assert_eq!(Reconciled { content: "  - a\n  - c\n  - b\n".to_string(), push: true, conflicts: 0, lost: 0 },
+                   reconcile("doc", Some(base), Some(changed), "  - a\n  - c\n"));
+        assert_eq!(Reconciled { content: "  - a\n  - c\n  - b\n".to_string(), push: true, conflicts: 0, lost: 0 },
+                   reconcile("doc", None, Some(changed), "  - a\n  - c\n"));
+
+        // a heading renamed on the server keeps the local items below it, and the local formatting
+        let base = "- Plans\n  ? Design\n  ? Build\n";
+        let local = "- Plans\n  ? Design\n  ? Build\n  ? Ship\n";
+        assert_eq!(Reconciled { content: "- Roadmap for Q3\n  ? Design\n  ? Build\n  ? Ship\n".to_string(),
+                                push: true, conflicts: 0, lost: 0 },
+                   reconcile("doc", Some(base), Some(local), "  - Roadmap for Q3\n    ? Design\n    ? Build\n"));
+    }
+}
@@ -1,39 +1,161 @@
 use yew::prelude::*;
 use yew::services::console::{ConsoleService};
-use itemtree::{ItemTree, Item, ItemId, ItemKind};
+use yew::services::dialog::DialogService;
+use yew::services::fetch::{FetchService, FetchTask, Response};
+use yew::services::storage::StorageService;
+use yew::services::timeout::{TimeoutService, TimeoutTask};
+use yew::services::Task;
+use yew::format::Text;
+use itemtree::{ItemTree, Item, ItemId, ItemKind, NodeKey, TextMode};
 use itemtree::ItemKind::*;
-use storage::LocalDocumentStorage;
+use storage::{DocumentStorage, StorageError};
+use remote::{self, RemoteStorage};
+use sync::{self, PendingChange};
+use search::{self, Query};
+use report::{self, Report, KindReport, ReportEntry, KindCounts};
+use diary;
+use library::DocumentInfo;
+use archive;
+use formats::Format;
+use html;
+use highlight::{self, Token};
+use browser_files;
+use revisions::Revision;
+use history::History;
+use diff::{self, DiffLine, LineStatus};
+use stdweb::web::Date as JsDate;
+use stdweb::web::event::IEvent;
 use std::mem;
-use std::collections::HashSet;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::time::Duration;
 
 pub struct Context {
     pub console: ConsoleService,
-    pub storage: LocalDocumentStorage
+    pub storage: Box<dyn DocumentStorage>,
+    pub dialog: DialogService,
+    pub remote: RemoteStorage,
+    pub fetch: FetchService,
+    pub timeout: TimeoutService,
+    /// Settings of the view, such as the last opened document
+    pub preferences: StorageService
 }
 
 pub enum Msg {
     // item tree manipulation
     Edit(ItemId, String),
+    /// Edits the text of a verbatim block, which keeps its kind
+    EditVerbatim(ItemId, String),
+    EditSyntax(ItemId, String),
     Delete(ItemId),
     Add(ItemId, usize),
     EditTitle(String),
+    /// Adds the most recently removed items last in the document
+    PasteRemoved,
+    Indent(ItemId),
+    Outdent(ItemId),
+    MoveUp(ItemId),
+    MoveDown(ItemId),
+    Undo,
+    Redo,
 
     // folding
     ToggleFold(ClickEvent, ItemId),
+    /// Unfolds the ancestors of an item, making it visible
+    Reveal(ItemId),
     FoldOffspring(ItemId,bool),
     ExpandOffspring(ItemId,bool),
     // save/restore
     Save,
     EditRestoreDocument(String),
     Restore,
+    /// Shows or hides the changes since the document was last saved
+    ToggleChanges,
+    /// Shows or hides the earlier versions of the document
+    ToggleRevisions,
+    /// Shows the changes between the nth newest revision and the document
+    CompareRevision(usize),
+    RestoreRevision(usize),
+    DismissError,
+    /// Saves if there are unsaved changes, sent a while after the last change
+    Autosave,
+
+    // document library
+    Open(String),
+    EditLibraryName(String),
+    RenameDocument(String),
+    DuplicateDocument(String),
+    DeleteDocument(String),
+
+    // diary
+    OpenDiary,
+    MigrateTasks,
+    DismissMigration,
 
     // pasting
     EditPastedDocument(String),
     LoadFromPasted,
 
+    // searching
+    Search(String),
+
+    // importing and exporting files
+    /// A file (name, content) to open as a new document
+    ImportFile(String, String),
+    /// Shows the document in the format under "As text", and downloads it in that format
+    ShowTextAs(Format),
+    Download,
+    DownloadHtml,
+    ExportAll,
+
+    // syncing with a document server
+    EditSyncUrl(String),
+    /// Pushes queued changes, and pulls changed documents
+    Sync,
+    Listed(Response<Text>),
+    Pulled(String, Response<Text>),
+    /// Response to pushing the title and content
+    Pushed(String, String, Response<Text>),
+    Deleted(String, Response<Text>),
+
     Noop
 }
 
+/// Consecutive edits of the same item (or the title) are undone together. Items are
+/// told apart by key, as ids change when the tree is compacted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EditGroup {
+    Item(NodeKey),
+    Title
+}
+
+/// `None` if the message doesn't change the tree, otherwise the group of the change.
+/// Switching to another document isn't undone, it starts a new history.
+fn undo_group(msg: &Msg, tree: &ItemTree) -> Option<Option<EditGroup>> {
+    match *msg {
+        Msg::Edit(id, _) | Msg::EditVerbatim(id, _) | Msg::EditSyntax(id, _) => Some(Some(EditGroup::Item(tree.key(id)))),
+        Msg::EditTitle(_) => Some(Some(EditGroup::Title)),
+        Msg::Delete(_) | Msg::Add(..) | Msg::MigrateTasks | Msg::Indent(_) | Msg::Outdent(_) |
+        Msg::MoveUp(_) | Msg::MoveDown(_) | Msg::PasteRemoved | Msg::RestoreRevision(_) => Some(None),
+        _ => None
+    }
+}
+
+/// Whether the message changes the document, so that it needs saving
+fn changes_document(msg: &Msg) -> bool {
+    match *msg {
+        Msg::Edit(..) | Msg::EditVerbatim(..) | Msg::EditSyntax(..) | Msg::EditTitle(_) | Msg::Delete(_) |
+        Msg::Add(..) | Msg::LoadFromPasted | Msg::ImportFile(..) | Msg::Indent(_) | Msg::Outdent(_) | Msg::MoveUp(_) | Msg::MoveDown(_) | Msg::PasteRemoved |
+        Msg::RestoreRevision(_) | Msg::Undo | Msg::Redo => true,
+        _ => false
+    }
+}
+
+const UNDO_LIMIT: usize = 100;
+const AUTOSAVE_DELAY_MS: u64 = 2000;
+const LAST_DOCUMENT_KEY: &'static str = "taskigt.view:last-document";
+
 /// The Taskigt-format consists of items, at different indentations
 /// that form a tree, subject to:
 /// * Each item is preceded by a bullet that indicates its type ('-','*','?','#','!', '|')
@@ -41,6 +163,8 @@ pub enum Msg {
 ///   this means the first bullet should be in column 2 (0 indexed)
 /// * In a well formatted document, each bullet is followed by a space
 /// * A line can be all whitespace
+/// * Consecutive verbatim lines form a block, a line like '|rust' or '|lang=sql' before
+///   them gives its syntax
 ///
 /// Any text file can be parsed into the Taskigt format, and transformed to a well formatted
 /// document. Such a transformation should only mean a few possible changes:
@@ -55,6 +179,7 @@ r#"  - Hierarchical item based note taking
     # Task (Done)
     ! Task (Blocked/Waiting)
     | Verbatim/quote
+      - Consecutive verbatim lines form a block, edited as a whole, and highlighted if a line like '|rust' or '|lang=sql' comes first
 
   - Controls
     | <ctrl/cmd> + *left-click*
@@ -65,44 +190,162 @@ r#"  - Hierarchical item based note taking
       - Move to next item (horizontally)
     | <shift> + <tab>
       - Move to previous item (horizontally)
+    | <alt> + <right> / <alt> + <left>
+      - Indent item (below the previous item) / outdent item (after its parent)
+    | <alt> + <up> / <alt> + <down>
+      - Move item up/down among its siblings
     | *clear content of item*
-      - Deletes the item, if there are no sub-items
+      - Deletes the item, along with its sub-items (after asking)
+      - [Paste removed] adds the most recently deleted items last in the document
     | <ctrl> + <z>
-      - Undo textual edits (note: not item removals/additions!)
+      - Undo any change to the document, including item removals/additions
+    | <ctrl> + <shift> + <z> or <ctrl> + <y>
+      - Redo
+
+  - Search
+    - Dims non matching items and unfolds the items containing matches
+    | deploy prod
+      - items containing both 'deploy' and 'prod' (case insensitive)
+    | kind:blocked kind:planned text:deploy
+      - blocked or planned items containing 'deploy'
+    | case:Deploy re:^v[0-9]+ ire:^todo
+      - case sensitive text, regular expressions
+
+  - Reports
+    - The 'Report' section lists all tasks per kind, with the items they are found under
+    - Clicking a task unfolds and jumps to it
+
+  - Diary
+    - [Today's diary]: opens (or creates) the document titled by today's date, e.g. 2018-07-14
+    - Unfinished tasks (planned, doing, blocked) on the most recent previous page can be copied to today
+      - They are marked '(migrated)' on the previous page
 
   - Persistence
     - [Save document]: saves the document to local web storage, using the current title as the document name
+      - Changing the title renames the document when saved, unless another document has that title
+      - Changes are also saved automatically a couple of seconds after the last edit, and the last document is reopened on the next visit
       | https://developer.mozilla.org/en-US/docs/Web/API/Storage/LocalStorage
     - [Restore document]: Restore the document with the supplied name from local storage
+    - Sync with a document server by entering its url and clicking [Sync now]
+      - Documents are pushed when saved, renamed or deleted, and changes made while offline are pushed at the next sync
+      - Documents changed both here and on the server are merged, conflicting changes are marked by '! Merge conflict' items
+      - Run a server locally with: cargo run --bin taskigt-server
+    - [Show changes]: shows what was added (+), removed (-), changed (~) and moved (>) since the last save
+    - [History]: lists the earlier versions kept when saving (at most one per 5 minutes, the oldest dropped when storage is full), to compare with or restore
 
   - Export/import from text
     - A textual representation of the current document is given under the 'As text' section
-    - Import to the current document (overwriting it!) by pasting into the 'Paste document' area, and clicking [Load pasted]
+    - Import as a new document by pasting into the 'Paste document' area, and clicking [Load pasted]
+    - [Markdown] under 'As text' shows the document as Markdown lists, with tasks as [ ] and [x] checkboxes
+    - [OPML] shows it as an outline for other outliners, such as Workflowy and OmniOutliner
+    - [Org] shows it as an orgmode outline, with TODO, DOING, DONE and WAITING headlines
+    - [JSON] shows it as JSON, for scripts and other tools (nested items, with a schema_version)
+    - Open text, Markdown, OPML, Org or JSON files as new documents by picking them under 'Files', or dropping them anywhere on the page
+    - [Download as .txt] saves the current document as a file, in the format shown under 'As text'
+    - [Download as web page (.html)] saves a read-only page of the current document, e.g. to publish it
+    - [Export all documents (.zip)] saves all stored documents
 "#;
 
 pub struct Model {
     curr_tree: ItemTree,
+    /// The title the document is stored under, replaced by an edited title when saved
+    document_title: String,
     restore_document_name: String,
     pasted_document: String,
-    hidden_node_ids: HashSet<ItemId>
+    text_format: Format,
+    hidden_node_ids: HashSet<ItemId>,
+    search_query: String,
+    /// Items matching the current search, `None` if not searching
+    search_matches: Option<HashSet<ItemId>>,
+    /// Folding to go back to when the search is cleared
+    folds_before_search: Option<HashSet<ItemId>>,
+    /// The previous diary page and its number of unfinished tasks, that can be
+    /// migrated to the currently open diary page
+    migration_offer: Option<(String, usize)>,
+    /// The stored documents, most recently modified first
+    documents: Vec<DocumentInfo>,
+    /// Name to rename or duplicate a document to
+    library_name: String,
+    history: History<ItemTree, EditGroup>,
+    /// The most recently removed subtree, for pasting back
+    last_removed: Option<ItemTree>,
+    /// The saved version of the document, while showing the changes since the save
+    saved_tree: Option<ItemTree>,
+    /// The earlier versions of the document, newest first, while shown
+    revisions: Option<Vec<Revision>>,
+    compared_revision: Option<usize>,
+    /// Requests to the document server, cancelled if dropped
+    sync_tasks: Vec<FetchTask>,
+    /// Shown in the url input, the url used is kept with the sync state
+    sync_url: String,
+    sync_status: String,
+    /// The last failure to load or store a document, until dismissed
+    error: Option<String>,
+    /// Whether the document has changed since it was saved, shared with the
+    /// beforeunload handler
+    unsaved: Rc<Cell<bool>>,
+    /// The pending autosave, postponed by each change
+    autosave_task: Option<TimeoutTask>
 }
 
 impl Component<Context> for Model {
     type Message = Msg;
     type Properties = ();
 
-    fn create(_: Self::Properties, _: &mut Env<Context, Self>) -> Self {
-        let curr_tree = ItemTree::parse("My items", README);
-        Model {
+    fn create(_: Self::Properties, context: &mut Env<Context, Self>) -> Self {
+        let last_document: Text = context.preferences.restore(LAST_DOCUMENT_KEY);
+        let curr_tree = last_document.ok()
+            .and_then(|title| context.storage.restore(&title).ok().map(|doc| ItemTree::parse(&title, &doc)))
+            .unwrap_or_else(|| {
+                let title = unique_title("My items", |title| context.storage.contains(title));
+                ItemTree::parse(&title, README)
+            });
+        let mut model = Model {
+            document_title: curr_tree.title(),
             curr_tree,
             restore_document_name: "".to_string(),
             pasted_document: "".to_string(),
-            hidden_node_ids: HashSet::new()
-        }
+            text_format: Format::Taskigt,
+            hidden_node_ids: HashSet::new(),
+            search_query: "".to_string(),
+            search_matches: None,
+            folds_before_search: None,
+            migration_offer: None,
+            documents: context.storage.list(),
+            library_name: "".to_string(),
+            history: History::new(UNDO_LIMIT),
+            last_removed: None,
+            saved_tree: None,
+            revisions: None,
+            compared_revision: None,
+            sync_tasks: vec![],
+            sync_url: context.remote.state.url.clone(),
+            sync_status: "".to_string(),
+            error: None,
+            unsaved: Rc::new(Cell::new(false)),
+            autosave_task: None
+        };
+        warn_before_unload(model.unsaved.clone());
+        browser_files::watch_imports(context.send_back(|(name, content)| Msg::ImportFile(name, content)));
+        model.sync(context);
+        model
     }
 
     fn update(&mut self, msg: Self::Message, context: &mut Env<Context, Self>) -> ShouldRender {
         let root = self.curr_tree.root();
+        let affects_search = changes_document(&msg) || match msg {
+            // another document, or the current one changed from elsewhere
+            Msg::Restore | Msg::Open(_) | Msg::OpenDiary | Msg::MigrateTasks |
+            Msg::Pulled(..) | Msg::Pushed(..) | Msg::Search(_) => true,
+            _ => false
+        };
+        let before = undo_group(&msg, &self.curr_tree).map(|group| (self.curr_tree.clone(), group));
+        if changes_document(&msg) {
+            self.unsaved.set(true);
+            let callback = context.send_back(|_| Msg::Autosave);
+            // replacing the pending autosave cancels it
+            self.autosave_task = Some(context.timeout.spawn(Duration::from_millis(AUTOSAVE_DELAY_MS), callback));
+        }
         match msg {
             Msg::Edit(id, new_value) => {
                 if new_value.len() > 0 {
@@ -116,11 +359,55 @@ impl Component<Context> for Model {
                     self.update(Msg::Delete(id), context);
                 }
             },
+            Msg::EditVerbatim(id, text) => {
+                if text.len() > 0 {
+                    self.curr_tree.nodes[id].text = text;
+                } else {
+                    self.update(Msg::Delete(id), context);
+                }
+            },
+            Msg::EditSyntax(id, syntax) => {
+                // a syntax is a single word, as in "|rust"
+                let syntax = syntax.split_whitespace().next().map(|syntax| syntax.to_string());
+                self.curr_tree.nodes[id].kind = Verbatim(syntax);
+            },
             Msg::EditTitle(title) => { self.curr_tree.nodes[root].text = title; },
+            Msg::Indent(id) => { self.curr_tree.indent(id); },
+            Msg::Outdent(id) => { self.curr_tree.outdent(id); },
+            Msg::MoveUp(id) => { self.curr_tree.move_up(id); },
+            Msg::MoveDown(id) => { self.curr_tree.move_down(id); },
+            Msg::Undo => {
+                if let Some(tree) = self.history.undo(self.curr_tree.clone()) {
+                    self.replace_tree(tree);
+                }
+            },
+            Msg::Redo => {
+                if let Some(tree) = self.history.redo(self.curr_tree.clone()) {
+                    self.replace_tree(tree);
+                }
+            },
             Msg::Delete(child_id) => {
-                let removed = self.curr_tree.remove_if_leaf(child_id);
-                if removed {
-                    context.console.log(&format!("del - {}", child_id));
+                let has_children = !self.curr_tree.nodes[child_id].children_ids.is_empty();
+                let confirmed = !has_children || {
+                    let question = format!("Delete '{}' and all items below it?",
+                                           self.curr_tree.nodes[child_id].text);
+                    context.dialog.confirm(&question)
+                };
+                if confirmed {
+                    if let Some(removed) = self.curr_tree.remove_subtree(child_id) {
+                        context.console.log(&format!("del - {} ({} items)", child_id,
+                                                     removed.item_count() + 1));
+                        self.last_removed = Some(removed);
+                    }
+                    if self.curr_tree.removed_count() > self.curr_tree.nodes.len() / 2 {
+                        self.compact_tree();
+                    }
+                }
+            }
+            Msg::PasteRemoved => {
+                if let Some(mut removed) = self.last_removed.take() {
+                    let kind = removed.nodes[removed.root()].kind.clone();
+                    self.curr_tree.append(root, kind, &mut removed);
                 }
             }
             Msg::Add(parent_id, child_pos) => {
@@ -140,6 +427,12 @@ impl Component<Context> for Model {
                     }
                 }
             }
+            Msg::Reveal(id) => {
+                self.hidden_node_ids.remove(&root);
+                for ancestor in self.curr_tree.ancestors(id) {
+                    self.hidden_node_ids.remove(&ancestor);
+                }
+            },
             Msg::FoldOffspring(id, and_self) => {
                 if and_self {
                     self.hidden_node_ids.insert(id);
@@ -157,34 +450,592 @@ impl Component<Context> for Model {
                 }
             },
             Msg::Save => {
-                context.storage.save(&self.curr_tree.title(),
-                                     build_text(self.curr_tree.root(),
-                                                &self.curr_tree.nodes));
+                self.save(context);
+            },
+            Msg::Autosave => {
+                self.autosave_task = None;
+                // a changed title may still be being typed, it's only taken when saving
+                if self.unsaved.get() && self.curr_tree.title() == self.document_title {
+                    self.save(context);
+                }
+            },
+            Msg::ToggleChanges => {
+                self.saved_tree = match self.saved_tree {
+                    Some(_) => None,
+                    None => {
+                        let title = self.curr_tree.title();
+                        let saved = context.storage.restore(&self.document_title)
+                            .map(|doc| ItemTree::parse(&title, &doc))
+                            .unwrap_or_else(|_| ItemTree::new(&title));
+                        Some(saved)
+                    }
+                };
+            },
+            Msg::ToggleRevisions => {
+                self.revisions = match self.revisions {
+                    Some(_) => None,
+                    None => Some(context.storage.revisions(&self.document_title))
+                };
+                self.compared_revision = None;
+            },
+            Msg::CompareRevision(nth) => {
+                self.compared_revision = Some(nth);
+            },
+            Msg::RestoreRevision(nth) => {
+                let revision = self.revisions.as_ref().and_then(|revisions| revisions.get(nth));
+                if let Some(revision) = revision {
+                    // keeps the current title
+                    let title = self.curr_tree.title();
+                    self.curr_tree = ItemTree::parse(&title, &revision.content);
+                    self.hidden_node_ids.clear();
+                }
+                self.compared_revision = None;
             },
             Msg::EditRestoreDocument(doc_name) => {
                 self.restore_document_name = doc_name;
             },
             Msg::Restore => {
-                let mut parsed_tree = context.storage.restore(&self.restore_document_name)
-                    .map(|doc| ItemTree::parse(&self.restore_document_name, &doc))
-                    .expect("load document failure");
-
-                mem::swap(&mut self.curr_tree, &mut parsed_tree);
+                let title = self.restore_document_name.clone();
+                self.update(Msg::Open(title), context);
+            },
+            Msg::Open(title) => {
+                self.save_if_unsaved(context);
+                match context.storage.restore(&title) {
+                    Ok(doc) => {
+                        let tree = ItemTree::parse(&title, &doc);
+                        self.switch_document(title, tree);
+                        self.remember_document(context);
+                    },
+                    // the current document stays open
+                    Err(e) => self.error = Some(format!("Opening failed: {}", e))
+                }
+            },
+            Msg::DismissError => {
+                self.error = None;
+            },
+            Msg::EditLibraryName(name) => {
+                self.library_name = name;
+            },
+            Msg::RenameDocument(title) => {
+                let new_title = self.library_name.clone();
+                match context.storage.rename(&title, &new_title) {
+                    Ok(()) => {
+                        if self.document_title == title {
+                            self.curr_tree.nodes[root].text = new_title.clone();
+                            self.document_title = new_title.clone();
+                            self.remember_document(context);
+                        }
+                        self.library_name.clear();
+                        self.queue_changes(vec![PendingChange::Delete(title), PendingChange::Push(new_title)],
+                                           context);
+                    },
+                    Err(e) => self.error = Some(format!("Renaming failed: {}", e))
+                }
+                self.documents = context.storage.list();
+            },
+            Msg::DuplicateDocument(title) => {
+                let new_title = self.library_name.clone();
+                match context.storage.duplicate(&title, &new_title) {
+                    Ok(()) => {
+                        self.library_name.clear();
+                        self.queue_changes(vec![PendingChange::Push(new_title)], context);
+                    },
+                    Err(e) => self.error = Some(format!("Duplicating failed: {}", e))
+                }
+                self.documents = context.storage.list();
+            },
+            Msg::DeleteDocument(title) => {
+                let question = format!("Delete the document '{}'?", title);
+                if context.dialog.confirm(&question) {
+                    if let Err(e) = context.storage.delete(&title) {
+                        self.error = Some(format!("Deleting failed: {}", e));
+                    }
+                    self.documents = context.storage.list();
+                    self.queue_changes(vec![PendingChange::Delete(title)], context);
+                }
+            },
+            Msg::OpenDiary => {
+                let now = JsDate::new();
+                match diary::Date::new(now.get_full_year(), now.get_month() as u32 + 1, now.get_date() as u32) {
+                    Some(today) => self.open_diary(today, context),
+                    // the current document stays open
+                    None => self.error = Some("Opening the diary failed: the browser gave an invalid date".to_string())
+                }
+            },
+            Msg::MigrateTasks => {
+                if let Some((previous_title, _)) = self.migration_offer.take() {
+                    match context.storage.restore(&previous_title) {
+                        Ok(doc) => {
+                            let mut previous = ItemTree::parse(&previous_title, &doc);
+                            let migrated = diary::migrate(&mut previous, &mut self.curr_tree);
+                            context.console.log(&format!("migrated {} tasks from {}",
+                                                         migrated, previous_title));
+                            let saved = context.storage.save(&previous_title,
+                                                             previous.to_text_with(TextMode::PreserveOriginal))
+                                .and_then(|_| context.storage.save(&self.document_title,
+                                                                   self.curr_tree.to_text_with(TextMode::PreserveOriginal)));
+                            match saved {
+                                Ok(()) => self.unsaved.set(false),
+                                Err(e) => self.error = Some(format!("Saving migrated tasks failed: {}", e))
+                            }
+                            self.documents = context.storage.list();
+                        },
+                        Err(e) => {
+                            self.error = Some(format!("Migrating tasks failed: {}", e));
+                        }
+                    }
+                }
+            },
+            Msg::DismissMigration => {
+                self.migration_offer = None;
             },
             Msg::EditPastedDocument(content) => {
                 self.pasted_document = content;
             },
             Msg::LoadFromPasted => {
-                let mut parsed_tree =
-                    ItemTree::parse("Pasted", &self.pasted_document);
-                mem::swap(&mut self.curr_tree, &mut parsed_tree);
+                self.save_if_unsaved(context);
+                // the autosave can't overwrite a stored document
+                let title = unique_title("Pasted", |title| context.storage.contains(title));
+                let tree = ItemTree::parse(&title, &self.pasted_document);
+                self.switch_document(title, tree);
+            }
+            Msg::Search(query) => {
+                self.search_query = query;
             }
+            Msg::ImportFile(name, content) => {
+                self.save_if_unsaved(context);
+                // the autosave can't overwrite a stored document
+                let title = unique_title(&archive::title_of(&name), |title| context.storage.contains(title));
+                match Format::of_file(&name).read(&title, &content) {
+                    Ok(tree) => self.switch_document(title, tree),
+                    Err(e) => {
+                        let error = StorageError::Parse(name, e.to_string());
+                        self.error = Some(format!("Importing failed: {}", error));
+                    }
+                }
+            },
+            Msg::ShowTextAs(format) => {
+                self.text_format = format;
+            },
+            Msg::Download => {
+                let format = self.text_format;
+                let text = format.write(&self.curr_tree);
+                browser_files::download(&archive::file_name(&self.curr_tree.title(), format.extension()),
+                                        text.as_bytes(), format.mime_type());
+            },
+            Msg::DownloadHtml => {
+                let page = html::to_html(&self.curr_tree);
+                browser_files::download(&archive::file_name(&self.curr_tree.title(), "html"),
+                                        page.as_bytes(), "text/html;charset=utf-8");
+            },
+            Msg::ExportAll => {
+                self.save_if_unsaved(context);
+                let documents: Vec<(String, String)> = context.storage.list().into_iter()
+                    .filter_map(|doc| context.storage.restore(&doc.title).ok().map(|content| (doc.title, content)))
+                    .collect();
+                browser_files::download("taskigt-documents.zip", &archive::export(&documents), "application/zip");
+            },
+            Msg::EditSyncUrl(url) => {
+                self.sync_url = url.clone();
+                context.remote.state.url = url;
+                context.remote.store_state();
+            },
+            Msg::Sync => self.sync(context),
+            Msg::Listed(response) => {
+                if response.status().is_success() {
+                    match remote::parse_list(response) {
+                        Ok(documents) => self.pull_changed(documents, context),
+                        Err(e) => self.sync_status = format!("Invalid document list: {}", e)
+                    }
+                } else {
+                    self.sync_failed(&response);
+                }
+            },
+            Msg::Pulled(title, response) => {
+                match remote::etag_of(&response) {
+                    Some(ref etag) if response.status().is_success() => {
+                        let content = response.into_body().unwrap_or_default();
+                        self.apply_remote(&title, etag, content, context);
+                    },
+                    _ => self.sync_failed(&response)
+                }
+            },
+            Msg::Pushed(title, content, response) => {
+                let etag = remote::etag_of(&response);
+                match (response.status().as_u16(), etag) {
+                    (200, Some(etag)) | (201, Some(etag)) => {
+                        context.remote.state.set_synced(&title, &etag, &content);
+                        // unless saved again while pushing
+                        if context.storage.restore(&title).ok().as_ref() == Some(&content) {
+                            context.remote.state.complete(&PendingChange::Push(title));
+                        }
+                        context.remote.store_state();
+                        self.report_sync(context);
+                    },
+                    (412, Some(etag)) => {
+                        // changed on the server since our last sync, the response has its version
+                        let remote_content = response.into_body().unwrap_or_default();
+                        self.apply_remote(&title, &etag, remote_content, context);
+                    },
+                    _ => self.sync_failed(&response)
+                }
+            },
+            Msg::Deleted(title, response) => {
+                match response.status().as_u16() {
+                    // if changed on the server since our last sync, the changed version
+                    // is pulled back at the next sync
+                    204 | 404 | 412 => {
+                        context.remote.state.forget(&title);
+                        context.remote.state.complete(&PendingChange::Delete(title));
+                        context.remote.store_state();
+                        self.report_sync(context);
+                    },
+                    _ => self.sync_failed(&response)
+                }
+            },
             Msg::Noop => {}
         }
+        if let Some((before, group)) = before {
+            // e.g. a cancelled delete or moving up the first item can't be undone
+            if before != self.curr_tree {
+                self.history.record(before, group);
+            }
+        }
+        if affects_search {
+            self.refresh_search(context);
+        }
         true
     }
 }
 
+impl Model {
+    /// Saves under the title of the document, renaming the stored document if the title
+    /// was changed. A title taken by another document is reverted, rather than overwriting it.
+    fn save(&mut self, context: &mut Env<Context, Self>) {
+        let mut changes = vec![];
+        let title = self.curr_tree.title();
+        if title != self.document_title {
+            let renamed = if context.storage.contains(&self.document_title) {
+                context.storage.rename(&self.document_title, &title)
+                    .map(|_| changes.push(PendingChange::Delete(self.document_title.clone())))
+            } else if context.storage.contains(&title) {
+                Err(StorageError::AlreadyExists(title.clone()))
+            } else {
+                Ok(())
+            };
+            match renamed {
+                Ok(()) => self.document_title = title,
+                Err(e) => {
+                    self.error = Some(format!("Renaming failed: {}", e));
+                    let root = self.curr_tree.root();
+                    self.curr_tree.nodes[root].text = self.document_title.clone();
+                }
+            }
+        }
+        let saved = context.storage.save(&self.document_title,
+                                         self.curr_tree.to_text_with(TextMode::PreserveOriginal));
+        match saved {
+            Ok(()) => {
+                self.unsaved.set(false);
+                self.autosave_task = None;
+            },
+            Err(e) => self.error = Some(format!("Saving failed: {}", e))
+        }
+        self.documents = context.storage.list();
+        self.remember_document(context);
+        changes.push(PendingChange::Push(self.document_title.clone()));
+        self.queue_changes(changes, context);
+        if self.saved_tree.is_some() {
+            self.saved_tree = Some(self.curr_tree.clone());
+        }
+        if self.revisions.is_some() {
+            self.revisions = Some(context.storage.revisions(&self.document_title));
+            self.compared_revision = None;
+        }
+    }
+
+    /// Before leaving the document
+    fn save_if_unsaved(&mut self, context: &mut Env<Context, Self>) {
+        if self.unsaved.get() {
+            self.save(context);
+        }
+    }
+
+    /// To reopen the document on the next start
+    fn remember_document(&self, context: &mut Env<Context, Self>) {
+        context.preferences.store(LAST_DOCUMENT_KEY, Ok(self.document_title.clone()));
+    }
+
+    /// Queues changes for the document server, and tries to push them right away
+    fn queue_changes(&mut self, changes: Vec<PendingChange>, context: &mut Env<Context, Self>) {
+        if context.remote.state.is_enabled() {
+            for change in changes {
+                context.remote.state.enqueue(change);
+            }
+            context.remote.store_state();
+            self.sync(context);
+        }
+    }
+
+    fn sync(&mut self, context: &mut Env<Context, Self>) {
+        if !context.remote.state.is_enabled() {
+            return;
+        }
+        self.sync_tasks.retain(|task| task.is_active());
+        for change in context.remote.state.queue.clone() {
+            match change {
+                PendingChange::Push(title) => self.push(title, context),
+                PendingChange::Delete(title) => {
+                    let request = context.remote.delete_request(&title);
+                    let callback = context.send_back(move |response| Msg::Deleted(title.clone(), response));
+                    self.sync_tasks.push(context.fetch.fetch(request, callback));
+                }
+            }
+        }
+        let request = context.remote.list_request();
+        let callback = context.send_back(Msg::Listed);
+        self.sync_tasks.push(context.fetch.fetch(request, callback));
+        self.report_sync(context);
+    }
+
+    /// Sends the stored version of the document to the server
+    fn push(&mut self, title: String, context: &mut Env<Context, Self>) {
+        let content = match context.storage.restore(&title) {
+            Ok(content) => content,
+            Err(_) => {
+                // deleted locally since
+                context.remote.state.complete(&PendingChange::Push(title));
+                context.remote.store_state();
+                return;
+            }
+        };
+        let request = context.remote.put_request(&title, content.clone());
+        let callback = context.send_back(move |response| {
+            Msg::Pushed(title.clone(), content.clone(), response)
+        });
+        self.sync_tasks.push(context.fetch.fetch(request, callback));
+    }
+
+    /// Fetches the documents that changed on the server since our last sync,
+    /// and pushes the ones the server doesn't have
+    fn pull_changed(&mut self, documents: Vec<sync::RemoteDocument>, context: &mut Env<Context, Self>) {
+        for doc in &documents {
+            let skip = {
+                let state = &context.remote.state;
+                let unchanged = state.synced(&doc.title).map_or(false, |synced| synced.etag == doc.etag);
+                unchanged || state.queue.contains(&PendingChange::Delete(doc.title.clone()))
+            };
+            if skip {
+                continue;
+            }
+            let title = doc.title.clone();
+            let request = context.remote.get_request(&title);
+            let callback = context.send_back(move |response| Msg::Pulled(title.clone(), response));
+            self.sync_tasks.push(context.fetch.fetch(request, callback));
+        }
+
+        for local in context.storage.list() {
+            let title = local.title;
+            if documents.iter().any(|doc| doc.title == title) ||
+                context.remote.state.queue.iter().any(|change| change.title() == title) {
+                continue;
+            }
+            let synced_content = context.remote.state.synced(&title).map(|synced| synced.content.clone());
+            let unchanged_locally = synced_content.is_some() &&
+                context.storage.restore(&title).ok() == synced_content;
+            context.remote.state.forget(&title);
+            if unchanged_locally {
+                // deleted on the server
+                if let Err(e) = context.storage.delete(&title) {
+                    self.error = Some(format!("Deleting failed: {}", e));
+                }
+            } else {
+                context.remote.state.enqueue(PendingChange::Push(title.clone()));
+                self.push(title, context);
+            }
+        }
+        context.remote.store_state();
+        self.documents = context.storage.list();
+        self.report_sync(context);
+    }
+
+    /// Merges a version from the server with ours, showing the result if it's the
+    /// current document (including unsaved changes), and pushes the result if needed
+    fn apply_remote(&mut self, title: &str, etag: &str, content: String, context: &mut Env<Context, Self>) {
+        let is_current = self.document_title == title;
+        let local = if is_current {
+            Some(self.curr_tree.to_text_with(TextMode::PreserveOriginal))
+        } else {
+            context.storage.restore(title).ok()
+        };
+        let base = context.remote.state.synced(title).map(|synced| synced.content.clone());
+        let reconciled = sync::reconcile(title, base.as_ref().map(|base| base.as_ref()),
+                                         local.as_ref().map(|local| local.as_ref()), &content);
+
+        context.remote.state.set_synced(title, etag, &content);
+        if context.storage.restore(title).ok().as_ref() != Some(&reconciled.content) {
+            if let Err(e) = context.storage.save(title, reconciled.content.clone()) {
+                self.error = Some(format!("Saving {} from the server failed: {}", title, e));
+            }
+            self.documents = context.storage.list();
+        }
+        if is_current && local.as_ref() != Some(&reconciled.content) {
+            self.history.record(self.curr_tree.clone(), None);
+            self.curr_tree = ItemTree::parse(&self.curr_tree.title(), &reconciled.content);
+        }
+        if is_current && context.storage.restore(title).ok().as_ref() == Some(&reconciled.content) {
+            self.unsaved.set(false);
+        }
+        if reconciled.push {
+            context.remote.state.enqueue(PendingChange::Push(title.to_string()));
+            self.push(title.to_string(), context);
+        }
+        context.remote.store_state();
+        self.report_sync(context);
+        if reconciled.conflicts > 0 {
+            self.sync_status = format!("{} merge conflict(s) in '{}'", reconciled.conflicts, title);
+        }
+        if reconciled.lost > 0 {
+            self.sync_status = format!("Merging '{}' lost {} item(s), it is pushed when saved \
+                                        (the previous version is in its history)", title, reconciled.lost);
+        }
+    }
+
+    fn report_sync(&mut self, context: &mut Env<Context, Self>) {
+        let waiting = context.remote.state.queue.len();
+        self.sync_status = if waiting == 0 {
+            "Synced".to_string()
+        } else {
+            format!("{} change(s) waiting", waiting)
+        };
+    }
+
+    fn sync_failed(&mut self, response: &Response<Text>) {
+        self.sync_status = format!("Sync failed ({}), changes are kept until the next sync",
+                                   response.status());
+    }
+
+    /// Opens another document, resetting what belonged to the previous one.
+    /// It starts a new undo history.
+    fn switch_document(&mut self, title: String, tree: ItemTree) {
+        self.curr_tree = tree;
+        self.document_title = title;
+        self.hidden_node_ids.clear();
+        self.folds_before_search = None;
+        self.history.clear();
+        self.saved_tree = None;
+        self.revisions = None;
+        self.error = None;
+        self.migration_offer = None;
+    }
+
+    /// Opens the diary page of the day, offering to migrate the tasks left on the previous page
+    fn open_diary(&mut self, today: diary::Date, context: &mut Env<Context, Self>) {
+        self.save_if_unsaved(context);
+        let title = today.title();
+        let page = context.storage.restore(&title)
+            .map(|doc| ItemTree::parse(&title, &doc))
+            .unwrap_or_else(|_| ItemTree::new(&title));
+        self.switch_document(title, page);
+        self.remember_document(context);
+
+        let storage = &context.storage;
+        let titles: Vec<String> = storage.list().into_iter().map(|doc| doc.title).collect();
+        self.migration_offer = diary::previous_page(today, titles.iter().map(|title| title.as_str()),
+                                                    |title| storage.restore(title).ok())
+            .map(|previous| {
+                let num_tasks = diary::unmigrated_tasks(&previous).len();
+                (previous.title(), num_tasks)
+            })
+            .filter(|&(_, num_tasks)| num_tasks > 0);
+    }
+
+    /// Puts back a version of the document from the undo history. Its ids may differ
+    /// (see `compact_tree`), so folded items are found by key.
+    fn replace_tree(&mut self, tree: ItemTree) {
+        let replaced = mem::replace(&mut self.curr_tree, tree);
+        let ids_by_key: HashMap<NodeKey, ItemId> = (0..self.curr_tree.nodes.len())
+            .filter(|id| !self.curr_tree.is_removed(*id))
+            .map(|id| (self.curr_tree.key(id), id))
+            .collect();
+        let remap_ids = |ids: &HashSet<ItemId>| -> HashSet<ItemId> {
+            ids.iter().filter_map(|id| ids_by_key.get(&replaced.key(*id)).cloned()).collect()
+        };
+        self.hidden_node_ids = remap_ids(&self.hidden_node_ids);
+        self.folds_before_search = self.folds_before_search.as_ref().map(&remap_ids);
+    }
+
+    /// Drops removed nodes from the current tree, keeping the folding
+    fn compact_tree(&mut self) {
+        let remap = self.curr_tree.compact();
+        let remap_ids = |ids: &HashSet<ItemId>| -> HashSet<ItemId> {
+            ids.iter().filter_map(|id| remap.get(id).cloned()).collect()
+        };
+        self.hidden_node_ids = remap_ids(&self.hidden_node_ids);
+        self.folds_before_search = self.folds_before_search.as_ref().map(&remap_ids);
+    }
+
+    /// Unfolds the ancestors of all search hits, on top of the folding from before the search
+    fn refresh_search(&mut self, context: &mut Env<Context, Self>) {
+        if self.search_query.trim().is_empty() {
+            self.search_matches = None;
+            if let Some(folds) = self.folds_before_search.take() {
+                self.hidden_node_ids = folds;
+            }
+            return;
+        }
+
+        match Query::parse(&self.search_query) {
+            Ok(query) => {
+                let folds = self.folds_before_search.take()
+                    .unwrap_or_else(|| self.hidden_node_ids.clone());
+                let hits = search::search(&self.curr_tree, &query);
+                self.hidden_node_ids = folds.clone();
+                self.hidden_node_ids.remove(&self.curr_tree.root());
+                for hit in &hits {
+                    for ancestor in &hit.ancestors {
+                        self.hidden_node_ids.remove(ancestor);
+                    }
+                }
+                self.search_matches = Some(hits.iter().map(|hit| hit.id).collect());
+                self.folds_before_search = Some(folds);
+            },
+            Err(e) => {
+                // likely a half typed query, keep the previous result
+                context.console.log(&format!("invalid search '{}': {}", self.search_query, e));
+            }
+        }
+    }
+}
+
+fn shortcut(e: KeyDownEvent) -> Msg {
+    let ctrl = e.ctrl_key() || e.meta_key();
+    let msg = match (ctrl, e.shift_key(), e.key().to_lowercase().as_ref()) {
+        (true, false, "z") => Msg::Undo,
+        (true, true, "z") | (true, _, "y") => Msg::Redo,
+        _ => return Msg::Noop
+    };
+    // our history replaces the browser's undo of text edits
+    e.prevent_default();
+    msg
+}
+
+/// Shortcuts for moving an item, and the general ones
+fn item_shortcut(id: ItemId, e: KeyDownEvent) -> Msg {
+    if !e.alt_key() {
+        return shortcut(e);
+    }
+    let msg = match e.key().as_ref() {
+        "ArrowRight" => Msg::Indent(id),
+        "ArrowLeft" => Msg::Outdent(id),
+        "ArrowUp" => Msg::MoveUp(id),
+        "ArrowDown" => Msg::MoveDown(id),
+        _ => return Msg::Noop
+    };
+    e.prevent_default();
+    msg
+}
+
 fn kind_class(kind: &ItemKind) -> &'static str {
     match *kind {
         Verbatim(_) => "node-value-verbatim",
@@ -192,12 +1043,24 @@ fn kind_class(kind: &ItemKind) -> &'static str {
     }
 }
 
-fn view_item(id: ItemId, item: &Item) -> Html<Context, Model> {
+fn match_class(id: ItemId, matches: &Option<HashSet<ItemId>>) -> &'static str {
+    match *matches {
+        Some(ref matching) if !matching.contains(&id) => "dimmed",
+        _ => ""
+    }
+}
+
+fn view_item(id: ItemId, item: &Item, matches: &Option<HashSet<ItemId>>) -> Html<Context, Model> {
+    if let Verbatim(ref syntax) = item.kind {
+        return view_verbatim(id, item, syntax, matches);
+    }
     let new_pos = item.children_ids.len();
     html! {
-        <input class=kind_class(&item.kind),
+        <input class=(kind_class(&item.kind), match_class(id, matches)),
+                id=item_anchor(id),
                 oninput=|e| Msg::Edit(id, e.value),
                 onclick=|e| Msg::ToggleFold(e, id),
+                onkeydown=|e| item_shortcut(id, e),
                 value=&item.display(),
                 onkeypress=|e| {
                        if e.key() == "Enter" { Msg::Add(id, new_pos) } else { Msg::Noop }
@@ -205,14 +1068,51 @@ fn view_item(id: ItemId, item: &Item) -> Html<Context, Model> {
     }
 }
 
-fn view_node(node: ItemId, nodes: &Vec<Item>, hidden: &HashSet<ItemId>, display_item: bool) -> Html<Context, Model> {
+/// A verbatim block as a textarea, above its highlighted text
+fn view_verbatim(id: ItemId, item: &Item, syntax: &Option<String>,
+                 matches: &Option<HashSet<ItemId>>) -> Html<Context, Model> {
+    let tokens = highlight::tokens(syntax.as_ref().map(|syntax| syntax.as_str()), &item.text);
+    html! {
+        <div class=("verbatim", match_class(id, matches)),>
+            <input class="verbatim-syntax",
+                placeholder="syntax",
+                value=syntax.clone().unwrap_or_default(),
+                oninput=|e| Msg::EditSyntax(id, e.value), />
+            <div class="verbatim-editor",>
+                <pre class="verbatim-highlight",>
+                    { for tokens.into_iter().map(view_token) }
+                    { "\n" }
+                </pre>
+                <textarea class="node-value-verbatim",
+                    id=item_anchor(id),
+                    rows=item.text.split('\n').count(),
+                    spellcheck="false",
+                    oninput=|e| Msg::EditVerbatim(id, e.value),
+                    onclick=|e| Msg::ToggleFold(e, id),
+                    onkeydown=|e| item_shortcut(id, e),
+                    value=&item.text,>
+                </textarea>
+            </div>
+        </div>
+    }
+}
+
+fn view_token((token, text): (Token, &str)) -> Html<Context, Model> {
+    match token.class() {
+        Some(class) => html! { <span class=class,>{ text }</span> },
+        None => html! { <span>{ text }</span> }
+    }
+}
+
+fn view_node(node: ItemId, nodes: &Vec<Item>, hidden: &HashSet<ItemId>,
+             matches: &Option<HashSet<ItemId>>, display_item: bool) -> Html<Context, Model> {
     let hide_ya_kids = hidden.contains(&node);
     let num_children = nodes[node].children_ids.len();
     html! {
         <li>
             {
                 if display_item {
-                    view_item(node, &nodes[node])
+                    view_item(node, &nodes[node], matches)
                 } else {
                     // hack for missing tag
                     html!{ <input type="hidden", />}
@@ -235,7 +1135,7 @@ fn view_node(node: ItemId, nodes: &Vec<Item>, hidden: &HashSet<ItemId>, display_
                     html!{
                         <ul class="nodes",>
                         { for nodes[node].children_ids.iter().map(|child_id| {
-                            view_node(child_id.clone(), nodes, hidden, true)
+                            view_node(child_id.clone(), nodes, hidden, matches, true)
                         })}
                         </ul>
                     }
@@ -246,37 +1146,309 @@ fn view_node(node: ItemId, nodes: &Vec<Item>, hidden: &HashSet<ItemId>, display_
     }
 }
 
-fn build_text_rec(level: usize, buffer: &mut String, node: ItemId, nodes: &Vec<Item>,
-                  display_item: bool) {
-    if display_item {
-        // assumes starts at level 1
-        if nodes[node].kind != BlankLine {
-            buffer.push_str(&" ".repeat((level-1) * 2));
-            buffer.push_str(&nodes[node].display());
-        }
-        buffer.push('\n');
+fn view_as_text(tree: &ItemTree, format: Format) -> Html<Context, Model> {
+    let text = match format {
+        Format::Taskigt => tree.to_text(),
+        _ => format.write(tree)
+    };
+    html! {
+        <div>
+            <h1>{ "As text" }</h1>
+            { for Format::ALL.iter().map(|&other| view_format_button(other, format)) }
+            <pre>{ text }</pre>
+        </div>
     }
+}
 
-    for child_id in &nodes[node].children_ids {
-        build_text_rec(level + 1, buffer, *child_id, nodes, true);
+fn view_format_button(format: Format, shown: Format) -> Html<Context, Model> {
+    html! {
+        <button onclick=|_| Msg::ShowTextAs(format), disabled=format == shown,>
+            { format.name() }
+        </button>
     }
 }
 
-fn build_text(start: ItemId, nodes: &Vec<Item>) -> String {
-    let mut buffer = String::new();
-    build_text_rec(1, &mut buffer, start, nodes, false);
-    buffer
+fn view_changes(saved_tree: &Option<ItemTree>, tree: &ItemTree) -> Html<Context, Model> {
+    match *saved_tree {
+        Some(ref saved_tree) => view_diff("Changes since saved", saved_tree, tree),
+        None => html! { <div></div> }
+    }
 }
 
-fn view_as_text(node: ItemId, nodes: &Vec<Item>) -> Html<Context, Model> {
+fn view_diff(heading: &str, old: &ItemTree, new: &ItemTree) -> Html<Context, Model> {
+    let changes = diff::diff(old, new);
+    let lines = diff::annotate(old, new, &changes);
     html! {
         <div>
-            <h1>{ "As text" }</h1>
-            <pre>{ build_text(node, nodes) }</pre>
+            <h1>{ format!("{} ({})", heading, changes.changes.len()) }</h1>
+            <pre class="changes",>
+            { for lines.iter().map(view_diff_line) }
+            </pre>
+        </div>
+    }
+}
+
+fn view_revisions(revisions: &Option<Vec<Revision>>, compared: Option<usize>,
+                  tree: &ItemTree) -> Html<Context, Model> {
+    let revisions = match *revisions {
+        Some(ref revisions) => revisions,
+        None => return html! { <div></div> }
+    };
+    let comparison = compared
+        .and_then(|nth| revisions.get(nth))
+        .map(|revision| {
+            let heading = format!("Changes since {}", format_modified(revision.saved));
+            view_diff(&heading, &ItemTree::parse(&tree.title(), &revision.content), tree)
+        })
+        .unwrap_or_else(|| html! { <div></div> });
+    html! {
+        <div>
+            <h1>{ format!("Earlier versions ({})", revisions.len()) }</h1>
+            <ul class="revisions",>
+                { for revisions.iter().enumerate().map(|(nth, revision)| view_revision(nth, revision)) }
+            </ul>
+            { comparison }
+        </div>
+    }
+}
+
+fn view_revision(nth: usize, revision: &Revision) -> Html<Context, Model> {
+    let item_count = ItemTree::parse("", &revision.content).item_count();
+    html! {
+        <li>
+            { format!("{} ({} items)", format_modified(revision.saved), item_count) }
+            <button onclick=|_| Msg::CompareRevision(nth),>{ "Compare" }</button>
+            <button onclick=|_| Msg::RestoreRevision(nth),>{ "Restore" }</button>
+        </li>
+    }
+}
+
+fn view_diff_line(line: &DiffLine) -> Html<Context, Model> {
+    let class = match line.status {
+        LineStatus::Unchanged if line.moved => "diff-moved",
+        LineStatus::Unchanged => "diff-unchanged",
+        LineStatus::Added => "diff-added",
+        LineStatus::Removed => "diff-removed",
+        LineStatus::Changed(..) => "diff-changed"
+    };
+    html! {
+        <div class=class,>{ line.to_string() }</div>
+    }
+}
+
+fn item_anchor(id: ItemId) -> String {
+    format!("item-{}", id)
+}
+
+fn view_report(tree: &ItemTree) -> Html<Context, Model> {
+    let report = report::report(tree);
+    html! {
+        <div>
+            <h1>{ "Report" }</h1>
+            { view_report_totals(tree, &report) }
+            { for report.sections.iter().map(|section| view_report_section(tree, section)) }
+        </div>
+    }
+}
+
+fn view_report_section(tree: &ItemTree, section: &KindReport) -> Html<Context, Model> {
+    html! {
+        <div>
+            <h2>{ format!("{} {:?} ({})", section.kind.symbol(), section.kind,
+                          section.entries.len()) }</h2>
+            <ul class="nodes",>
+            { for section.entries.iter().map(|entry| view_report_entry(tree, entry)) }
+            </ul>
+        </div>
+    }
+}
+
+fn view_report_entry(tree: &ItemTree, entry: &ReportEntry) -> Html<Context, Model> {
+    let id = entry.id;
+    let mut crumbs = entry.breadcrumb.join(" > ");
+    if !crumbs.is_empty() {
+        crumbs.push_str(" > ");
+    }
+    let counts = if entry.counts.total() > 0 {
+        format!(" [{}]", format_counts(&entry.counts))
+    } else {
+        "".to_string()
+    };
+    html! {
+        <li class="node-value",>
+            { crumbs }
+            <a href=format!("#{}", item_anchor(id)),
+                onclick=|_| Msg::Reveal(id),>
+                { &tree.nodes[id].text }
+            </a>
+            { counts }
+        </li>
+    }
+}
+
+fn view_report_totals(tree: &ItemTree, report: &Report) -> Html<Context, Model> {
+    html! {
+        <ul class="nodes",>
+        { for report.subtrees.iter().map(|&(id, ref counts)| view_subtree_counts(tree, id, counts)) }
+        </ul>
+    }
+}
+
+fn view_subtree_counts(tree: &ItemTree, id: ItemId, counts: &KindCounts) -> Html<Context, Model> {
+    html! {
+        <li class="node-value",>
+            <a href=format!("#{}", item_anchor(id)),
+                onclick=|_| Msg::Reveal(id),>
+                { &tree.nodes[id].text }
+            </a>
+            { format!(" [{}]", format_counts(counts)) }
+        </li>
+    }
+}
+
+fn format_counts(counts: &KindCounts) -> String {
+    report::TASK_KINDS.iter()
+        .map(|kind| format!("{}{}", kind.symbol(), counts.get(kind)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Asks before leaving the page while there are unsaved changes
+fn warn_before_unload(unsaved: Rc<Cell<bool>>) {
+    let is_unsaved = move || unsaved.get();
+    js! { @(no_return)
+        var is_unsaved = @{is_unsaved};
+        window.addEventListener("beforeunload", function(event) {
+            if (is_unsaved()) {
+                event.preventDefault();
+                // shown by older browsers
+                event.returnValue = "There are unsaved changes";
+                return event.returnValue;
+            }
+        });
+    }
+}
+
+fn view_error(error: &Option<String>) -> Html<Context, Model> {
+    match *error {
+        Some(ref message) => html! {
+            <div class="error",>
+                { message }
+                <button onclick=|_| Msg::DismissError,>
+                    { "Dismiss" }
+                </button>
+            </div>
+        },
+        // hack for missing tag
+        None => html! { <input type="hidden", /> }
+    }
+}
+
+fn view_migration_offer(offer: &Option<(String, usize)>) -> Html<Context, Model> {
+    match *offer {
+        Some((ref previous_title, num_tasks)) => html! {
+            <div>
+                <button onclick=|_| Msg::MigrateTasks,>
+                    { format!("Copy {} unfinished tasks from {}", num_tasks, previous_title) }
+                </button>
+                <button onclick=|_| Msg::DismissMigration,>
+                    { "No thanks" }
+                </button>
+            </div>
+        },
+        // hack for missing tag
+        None => html! { <input type="hidden", /> }
+    }
+}
+
+fn format_modified(modified: f64) -> String {
+    if modified <= 0.0 {
+        return "unknown".to_string();
+    }
+    let date = JsDate::from_time(modified);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", date.get_full_year(), date.get_month() + 1,
+            date.get_date(), date.get_hours(), date.get_minutes())
+}
+
+fn view_library(documents: &[DocumentInfo], current_title: &str,
+                library_name: &str) -> Html<Context, Model> {
+    html! {
+        <div>
+            <h2>{ "Documents" }</h2>
+            <input placeholder="New name (rename/duplicate)",
+                oninput=|e| Msg::EditLibraryName(e.value),
+                value=library_name, />
+            <ul class="library",>
+                { for documents.iter().map(|doc| view_library_entry(doc, current_title)) }
+            </ul>
         </div>
     }
 }
 
+fn view_library_entry(doc: &DocumentInfo, current_title: &str) -> Html<Context, Model> {
+    let class = if doc.title == current_title { "library-current" } else { "" };
+    let (open, rename, duplicate, delete) =
+        (doc.title.clone(), doc.title.clone(), doc.title.clone(), doc.title.clone());
+    html! {
+        <li class=class,>
+            <button class="library-open", onclick=|_| Msg::Open(open.clone()),>
+                { &doc.title }
+            </button>
+            <div class="library-details",>
+                { format!("{} items, {}", doc.item_count, format_modified(doc.modified)) }
+            </div>
+            <button onclick=|_| Msg::RenameDocument(rename.clone()),>{ "Rename" }</button>
+            <button onclick=|_| Msg::DuplicateDocument(duplicate.clone()),>{ "Duplicate" }</button>
+            <button onclick=|_| Msg::DeleteDocument(delete.clone()),>{ "Delete" }</button>
+        </li>
+    }
+}
+
+fn view_sync(url: &str, status: &str) -> Html<Context, Model> {
+    html! {
+        <span>
+            <input placeholder="Sync server, e.g. http://localhost:8090",
+                oninput=|e| Msg::EditSyncUrl(e.value),
+                value=url, />
+            <button onclick=|_| Msg::Sync,>
+                { "Sync now" }
+            </button>
+            <span class="status",>{ status }</span>
+        </span>
+    }
+}
+
+fn view_files(format: Format) -> Html<Context, Model> {
+    html! {
+        <div>
+            <h2>{ "Files" }</h2>
+            <input type="file", class=browser_files::IMPORT_CLASS,
+                accept=".txt,.md,.markdown,.opml,.org,.json,text/plain,text/markdown,text/x-opml,text/x-org,application/json",
+                multiple="", />
+            <button onclick=|_| Msg::Download,>
+                { format!("Download as .{}", format.extension()) }
+            </button>
+            <button onclick=|_| Msg::DownloadHtml,>
+                { "Download as web page (.html)" }
+            </button>
+            <button onclick=|_| Msg::ExportAll,>
+                { "Export all documents (.zip)" }
+            </button>
+        </div>
+    }
+}
+
+/// The title, or if taken, the title with the first free number, e.g. "Plans (2)"
+fn unique_title<F: Fn(&str) -> bool>(title: &str, is_taken: F) -> String {
+    let mut unique = title.to_string();
+    let mut copy = 1;
+    while is_taken(&unique) {
+        copy += 1;
+        unique = format!("{} ({})", title, copy);
+    }
+    unique
+}
+
 fn paste_area(content: &str) -> Html<Context, Model> {
     html! {
         <div>
@@ -298,10 +1470,12 @@ fn paste_area(content: &str) -> Html<Context, Model> {
 impl Renderable<Context, Model> for Model {
     fn view(&self) -> Html<Context, Self> {
         html! {
-            <div>
+            <div onkeydown=|e| shortcut(e),>
                 <nav class="menu",>
+                    { view_library(&self.documents, &self.document_title, &self.library_name) }
                 </nav>
                 <div>
+                    { view_error(&self.error) }
                     <div>
                         <input class="document-title",
                             oninput=|e| Msg::EditTitle(e.value),
@@ -312,6 +1486,24 @@ impl Renderable<Context, Model> for Model {
                         <button onclick=|_| Msg::Save,>
                             { "Save document" }
                         </button>
+                        <span class="status",>
+                            { if self.unsaved.get() { "Unsaved changes" } else { "Saved" } }
+                        </span>
+                        <button onclick=|_| Msg::Undo, disabled=!self.history.can_undo(),>
+                            { "Undo" }
+                        </button>
+                        <button onclick=|_| Msg::Redo, disabled=!self.history.can_redo(),>
+                            { "Redo" }
+                        </button>
+                        <button onclick=|_| Msg::PasteRemoved, disabled=self.last_removed.is_none(),>
+                            { "Paste removed" }
+                        </button>
+                        <button onclick=|_| Msg::ToggleChanges,>
+                            { if self.saved_tree.is_some() { "Hide changes" } else { "Show changes" } }
+                        </button>
+                        <button onclick=|_| Msg::ToggleRevisions,>
+                            { if self.revisions.is_some() { "Hide history" } else { "History" } }
+                        </button>
                         <br />
                         <button onclick=|_| Msg::FoldOffspring(0, false),>
                             { "Fold all" }
@@ -326,12 +1518,29 @@ impl Renderable<Context, Model> for Model {
                         <button onclick=|_| Msg::Restore, >
                             { "Restore document" }
                         </button>
+                        <br />
+                        <button onclick=|_| Msg::OpenDiary,>
+                            { "Today's diary" }
+                        </button>
+                        { view_migration_offer(&self.migration_offer) }
+                        <br />
+                        { view_sync(&self.sync_url, &self.sync_status) }
+                        <br />
+                        <input class="search",
+                            placeholder="Search, e.g. kind:blocked deploy",
+                            oninput=|e| Msg::Search(e.value),
+                            value=&self.search_query, />
                     </div>
                     <ul class="nodes",>
-                        { view_node(self.curr_tree.root(), &self.curr_tree.nodes, &self.hidden_node_ids, false) }
+                        { view_node(self.curr_tree.root(), &self.curr_tree.nodes, &self.hidden_node_ids,
+                                    &self.search_matches, false) }
                     </ul>
                 </div>
-                { view_as_text(self.curr_tree.root(), &self.curr_tree.nodes) }
+                { view_changes(&self.saved_tree, &self.curr_tree) }
+                { view_revisions(&self.revisions, self.compared_revision, &self.curr_tree) }
+                { view_report(&self.curr_tree) }
+                { view_as_text(&self.curr_tree, self.text_format) }
+                { view_files(self.text_format) }
                 { paste_area(&self.pasted_document) }
             </div>
         }
@@ -345,7 +1554,7 @@ mod tests {
     #[test]
     fn readme_is_well_formatted() {
         let tree = ItemTree::parse("readme", README);
-        let tree_as_text = build_text(0, &tree.nodes);
+        let tree_as_text = tree.to_text();
         assert_eq!(README, tree_as_text);
     }
 }
\ No newline at end of file